@@ -0,0 +1,87 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use engine_lib::{ClientId, Config, Engine, Transaction, TransactionType, TxId};
+use rust_decimal::Decimal;
+use rustc_hash::FxHashMap;
+
+const KEYS: u32 = 60_000;
+
+/// inserts and looks up every key, like the client map does for every transaction
+fn insert_and_get<K: std::hash::Hash + Eq + Copy>(keys: &[K]) -> u64 {
+    let mut map: FxHashMap<K, u64> = FxHashMap::default();
+    for key in keys {
+        *map.entry(*key).or_default() += 1;
+    }
+    keys.iter().map(|key| map[key]).sum()
+}
+
+/// compares the map lookups of the possible id widths and of string ids interned to integers
+pub fn map_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("fx hash map ids");
+    let u16_keys: Vec<u16> = (0..KEYS).map(|key| key as u16).collect();
+    let u32_keys: Vec<u32> = (0..KEYS).collect();
+    let u64_keys: Vec<u64> = (0..KEYS).map(u64::from).collect();
+    let string_keys: Vec<String> = (0..KEYS).map(|key| format!("client-{}", key)).collect();
+
+    group.bench_function(BenchmarkId::new("insert and get", "u16"), |b| {
+        b.iter(|| insert_and_get(black_box(&u16_keys)))
+    });
+    group.bench_function(BenchmarkId::new("insert and get", "u32"), |b| {
+        b.iter(|| insert_and_get(black_box(&u32_keys)))
+    });
+    group.bench_function(BenchmarkId::new("insert and get", "u64"), |b| {
+        b.iter(|| insert_and_get(black_box(&u64_keys)))
+    });
+    group.bench_function(BenchmarkId::new("insert and get", "interned string"), |b| {
+        b.iter(|| {
+            let mut interner: FxHashMap<&str, u32> = FxHashMap::default();
+            let keys: Vec<u32> = black_box(&string_keys)
+                .iter()
+                .map(|key| {
+                    let next = interner.len() as u32;
+                    *interner.entry(key.as_str()).or_insert(next)
+                })
+                .collect();
+            insert_and_get(&keys)
+        })
+    });
+    group.finish();
+}
+
+/// processes deposits and withdrawals with the id types of the build,
+/// compare `cargo bench --bench id_width` with `cargo bench --bench id_width --features wide-ids`
+pub fn engine_benchmark(c: &mut Criterion) {
+    // KEYS fits into the narrow client ids as well
+    let transactions: Vec<(ClientId, TxId)> = (0..KEYS * 4)
+        .map(|tx| ((tx % KEYS) as ClientId, tx as TxId))
+        .collect();
+
+    c.bench_function("engine with configured id widths", |b| {
+        b.iter(|| {
+            let mut engine = Engine::new(Config::default());
+            for (index, (client_id, tx_id)) in transactions.iter().enumerate() {
+                // every client alternates between deposits and withdrawals, so all of them are applied
+                let r#type = if (index / KEYS as usize).is_multiple_of(2) {
+                    TransactionType::Deposit
+                } else {
+                    TransactionType::Withdrawal
+                };
+                engine
+                    .process(Transaction::new(
+                        r#type,
+                        *client_id,
+                        *tx_id,
+                        Some(Decimal::ONE),
+                    ))
+                    .unwrap();
+            }
+            black_box(engine.rows())
+        })
+    });
+}
+
+criterion_group!(
+    name = benches;
+    config = Criterion::default().significance_level(0.1).sample_size(20);
+    targets = map_benchmark, engine_benchmark
+);
+criterion_main!(benches);
@@ -1,10 +1,25 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use engine_lib::core_logic;
+use engine_lib::{core_logic, generate, Config, GeneratorConfig};
+use std::fs::File;
 
 /// was used to iterate on the performance of the sync version
 pub fn criterion_benchmark(c: &mut Criterion) {
+    let path = std::env::temp_dir().join("engine_bench_test_file.csv");
+    let generator_config = GeneratorConfig {
+        rows: 1_000_000,
+        ..Default::default()
+    };
+    generate(&generator_config, File::create(&path).unwrap()).unwrap();
+    let path = path.to_str().unwrap();
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let config = Config::default();
     c.bench_function("bench core logic", |b| {
-        b.iter(|| core_logic(black_box("./files/large_test_file.csv")).unwrap())
+        b.iter(|| {
+            runtime
+                .block_on(core_logic(black_box(&[path]), &config))
+                .unwrap()
+        })
     });
 }
 
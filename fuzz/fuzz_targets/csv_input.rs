@@ -0,0 +1,34 @@
+#![no_main]
+
+mod invariants;
+
+use engine_lib::{process_csv, Config, CsvDialect, Engine};
+use invariants::Invariants;
+use libfuzzer_sys::fuzz_target;
+
+// the first byte picks the dialect, the rest is the csv input
+fuzz_target!(|data: &[u8]| {
+    let (options, input) = match data.split_first() {
+        Some((options, input)) => (*options, input),
+        None => return,
+    };
+    let dialect = CsvDialect {
+        delimiter: if options & 1 == 0 { b',' } else { b';' },
+        has_headers: options & 2 == 0,
+        case_insensitive_types: options & 4 != 0,
+        decimal_comma: options & 8 != 0,
+        ..CsvDialect::default()
+    };
+
+    let mut engine = Engine::new(Config::default()).with_ledger();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    // malformed rows end the input with an error, they must not panic
+    let _ = runtime.block_on(process_csv(input, &dialect, &mut engine));
+
+    let mut invariants = Invariants::default();
+    for entry in engine.drain_ledger() {
+        invariants.check(entry);
+    }
+});
@@ -0,0 +1,36 @@
+use engine_lib::{ClientId, LedgerEntry, LedgerEvent};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// Checks every ledger entry against the previous entry of the same client
+#[derive(Default)]
+pub struct Invariants {
+    last: HashMap<ClientId, LedgerEntry>,
+}
+
+impl Invariants {
+    pub fn check(&mut self, entry: LedgerEntry) {
+        assert_eq!(entry.total, entry.available + entry.held, "{:?}", entry);
+        assert!(entry.held >= Decimal::ZERO, "{:?}", entry);
+
+        let (previous_total, previous_locked) = match self.last.get(&entry.client_id) {
+            Some(previous) => (previous.total, previous.locked),
+            None => (Decimal::ZERO, false),
+        };
+        // reversals credit the available funds and may unlock, nothing else changes a locked client
+        if previous_locked {
+            assert!(
+                matches!(
+                    entry.event,
+                    LedgerEvent::CreditAvailable(_) | LedgerEvent::Unlock
+                ),
+                "{:?}",
+                entry
+            );
+        }
+        if let LedgerEvent::DebitHeld(_) = entry.event {
+            assert!(entry.total < previous_total, "{:?}", entry);
+        }
+        self.last.insert(entry.client_id, entry);
+    }
+}
@@ -0,0 +1,84 @@
+#![no_main]
+
+mod invariants;
+
+use arbitrary::Arbitrary;
+use engine_lib::{Config, Engine, Rejection, Transaction, TransactionType};
+use invariants::Invariants;
+use libfuzzer_sys::fuzz_target;
+use rust_decimal::Decimal;
+use std::collections::HashSet;
+
+#[derive(Arbitrary, Debug)]
+enum Type {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Reversal,
+}
+
+/// small client and transaction ids so references hit. Amounts cover the whole range of decimals
+/// with up to four decimal places, the precision the balances are exact for.
+#[derive(Arbitrary, Debug)]
+struct FuzzTransaction {
+    r#type: Type,
+    client: u8,
+    tx: u8,
+    amount: Option<(u32, u32, u32, bool, u8)>,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    unlock_on_reversal: bool,
+    transactions: Vec<FuzzTransaction>,
+}
+
+fuzz_target!(|input: Input| {
+    let config = Config {
+        unlock_on_reversal: input.unlock_on_reversal,
+        ..Config::default()
+    };
+    let mut engine = Engine::new(config).with_ledger();
+    let mut invariants = Invariants::default();
+    let mut locked = HashSet::new();
+
+    for transaction in input.transactions {
+        let r#type = match transaction.r#type {
+            Type::Deposit => TransactionType::Deposit,
+            Type::Withdrawal => TransactionType::Withdrawal,
+            Type::Dispute => TransactionType::Dispute,
+            Type::Resolve => TransactionType::Resolve,
+            Type::Chargeback => TransactionType::Chargeback,
+            Type::Reversal => TransactionType::Reversal,
+        };
+        let amount = transaction.amount.map(|(lo, mid, hi, negative, scale)| {
+            Decimal::from_parts(lo, mid, hi, negative, u32::from(scale % 5))
+        });
+        let client = transaction.client.into();
+
+        let result = engine.process(Transaction::new(
+            r#type,
+            client,
+            transaction.tx.into(),
+            amount,
+        ));
+        if locked.contains(&client) && r#type != TransactionType::Reversal {
+            assert_eq!(result, Err(Rejection::ClientLocked));
+        }
+
+        let entries: Vec<_> = engine.drain_ledger().collect();
+        if result.is_err() {
+            assert!(entries.is_empty());
+        }
+        for entry in entries {
+            if entry.locked {
+                locked.insert(entry.client_id);
+            } else {
+                locked.remove(&entry.client_id);
+            }
+            invariants.check(entry);
+        }
+    }
+});
@@ -0,0 +1,9 @@
+#!/bin/sh
+# Copies the csv files of the integration tests into the corpus of the csv_input target.
+# The target takes the first byte as the dialect options, a zero byte in front selects the default dialect.
+set -e
+cd "$(dirname "$0")"
+mkdir -p corpus/csv_input
+for f in ../files/*.csv; do
+    { printf '\0'; cat "$f"; } > "corpus/csv_input/$(basename "$f")"
+done
@@ -1,16 +1,218 @@
+use crate::transaction::ClientId;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Debug)]
-pub(crate) struct ArchivedDeposit {
+/// Lifecycle of a deposit: Settled → Disputed → ChargedBack → Reversed.
+/// A resolved dispute goes back to Settled, a reversed deposit can be disputed again.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DepositState {
+    Settled,
+    Disputed,
+    ChargedBack,
+    Reversed,
+}
+
+/// Reasons for a dispute, resolve, chargeback or reversal to be rejected by a deposit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransitionError {
+    /// the transaction is not allowed in the current state of the deposit
+    InvalidState(DepositState),
+    /// the amount is not positive or exceeds what can be disputed / resolved / charged back / reversed
+    InvalidAmount,
+    /// the transaction was issued by a different client than the deposit
+    ClientMismatch,
+}
+
+/// Counts of all transitions the deposit went through and the row of the input that caused the latest of each
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepositHistory {
+    pub deposited_at: u64,
+    pub disputes: u32,
+    pub last_disputed_at: Option<u64>,
+    pub resolves: u32,
+    pub last_resolved_at: Option<u64>,
+    pub chargebacks: u32,
+    pub last_charged_back_at: Option<u64>,
+    pub reversals: u32,
+    pub last_reversed_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchivedDeposit {
+    pub(crate) client_id: ClientId,
     pub(crate) amount: Decimal,
-    pub(crate) disputed: bool,
+    /// sum of all open disputes, several partial disputes can be open at the same time
+    pub(crate) disputed: Decimal,
+    pub(crate) charged_back: Decimal,
+    pub(crate) state: DepositState,
+    pub(crate) history: DepositHistory,
 }
 
 impl ArchivedDeposit {
-    pub fn new(amount: Decimal) -> Self {
+    pub(crate) fn new(client_id: ClientId, amount: Decimal, row: u64) -> Self {
         ArchivedDeposit {
+            client_id,
             amount,
-            disputed: false,
+            disputed: Decimal::ZERO,
+            charged_back: Decimal::ZERO,
+            state: DepositState::Settled,
+            history: DepositHistory {
+                deposited_at: row,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
+    }
+
+    pub fn amount(&self) -> Decimal {
+        self.amount
+    }
+
+    pub fn disputed(&self) -> Decimal {
+        self.disputed
+    }
+
+    pub fn charged_back(&self) -> Decimal {
+        self.charged_back
+    }
+
+    pub fn state(&self) -> DepositState {
+        self.state
+    }
+
+    pub fn history(&self) -> &DepositHistory {
+        &self.history
+    }
+
+    /// part of the deposit that can still be disputed
+    pub fn undisputed(&self) -> Decimal {
+        self.amount - self.charged_back - self.disputed
+    }
+
+    /// Holds the given amount or, without amount, everything that is not yet disputed.
+    /// A deposit can be disputed again after its chargeback was reversed, but not while it is charged back.
+    pub(crate) fn dispute(
+        &mut self,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        row: u64,
+    ) -> Result<Decimal, TransitionError> {
+        self.validate(
+            client_id,
+            &[
+                DepositState::Settled,
+                DepositState::Disputed,
+                DepositState::Reversed,
+            ],
+        )?;
+        let amount = validate_amount(amount, self.undisputed())?;
+
+        self.disputed += amount;
+        self.state = DepositState::Disputed;
+        self.history.disputes += 1;
+        self.history.last_disputed_at = Some(row);
+        Ok(amount)
+    }
+
+    /// Releases the given amount or, without amount, everything that is disputed
+    pub(crate) fn resolve(
+        &mut self,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        row: u64,
+    ) -> Result<Decimal, TransitionError> {
+        self.validate(client_id, &[DepositState::Disputed])?;
+        let amount = validate_amount(amount, self.disputed)?;
+
+        self.disputed -= amount;
+        if self.disputed.is_zero() {
+            self.state = if self.charged_back.is_zero() {
+                DepositState::Settled
+            } else {
+                DepositState::ChargedBack
+            };
+        }
+        self.history.resolves += 1;
+        self.history.last_resolved_at = Some(row);
+        Ok(amount)
+    }
+
+    /// Charges back the given amount or, without amount, everything that is disputed
+    pub(crate) fn charge_back(
+        &mut self,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        row: u64,
+    ) -> Result<Decimal, TransitionError> {
+        self.validate(client_id, &[DepositState::Disputed])?;
+        let amount = validate_amount(amount, self.disputed)?;
+
+        self.disputed -= amount;
+        self.charged_back += amount;
+        if self.disputed.is_zero() {
+            self.state = DepositState::ChargedBack;
+        }
+        self.history.chargebacks += 1;
+        self.history.last_charged_back_at = Some(row);
+        Ok(amount)
+    }
+
+    /// Restores the given amount or, without amount, everything that was charged back
+    pub(crate) fn reverse(
+        &mut self,
+        client_id: ClientId,
+        amount: Option<Decimal>,
+        row: u64,
+    ) -> Result<Decimal, TransitionError> {
+        self.validate(client_id, &[DepositState::ChargedBack])?;
+        let amount = validate_amount(amount, self.charged_back)?;
+
+        self.charged_back -= amount;
+        if self.charged_back.is_zero() {
+            self.state = DepositState::Reversed;
+        }
+        self.history.reversals += 1;
+        self.history.last_reversed_at = Some(row);
+        Ok(amount)
+    }
+
+    fn validate(
+        &self,
+        client_id: ClientId,
+        allowed: &[DepositState],
+    ) -> Result<(), TransitionError> {
+        if client_id != self.client_id {
+            Err(TransitionError::ClientMismatch)
+        } else if !allowed.contains(&self.state) {
+            Err(TransitionError::InvalidState(self.state))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// without amount the whole `limit` is used
+fn validate_amount(amount: Option<Decimal>, limit: Decimal) -> Result<Decimal, TransitionError> {
+    let amount = amount.unwrap_or(limit);
+    if amount > Decimal::ZERO && amount <= limit {
+        Ok(amount)
+    } else {
+        Err(TransitionError::InvalidAmount)
+    }
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransitionError::InvalidState(state) => {
+                write!(f, "not allowed for {:?} deposit", state)
+            }
+            TransitionError::InvalidAmount => write!(f, "invalid amount"),
+            TransitionError::ClientMismatch => write!(f, "deposit belongs to another client"),
         }
     }
 }
@@ -0,0 +1,200 @@
+use crate::csv_writer_with_header;
+use crate::transaction::{ClientId, TxId};
+use crate::{ClientsDataStructure, TransactionsDataStructure};
+use rust_decimal::Decimal;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::error::Error;
+use std::io::Write;
+
+/// A global invariant that doesn't hold after processing
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Violation {
+    client: ClientId,
+    /// the deposit for violations of a single deposit
+    tx: Option<TxId>,
+    invariant: &'static str,
+    expected: Decimal,
+    actual: Decimal,
+}
+
+/// Checks the state after processing against the archived deposits and the withdrawals, which are kept apart from
+/// the balances and statistics of the clients. Violations are ordered by client and deposit:
+/// - `total`: the total of every client equals its archived deposits without the charged back part minus its withdrawals
+/// - `held`: the held funds of every client equal the disputed amounts of its archived deposits
+/// - `unknown_client`: no archived deposit is disputed for a client that doesn't exist
+pub(crate) fn audit(
+    clients: &ClientsDataStructure,
+    archived_deposits: &TransactionsDataStructure,
+    withdrawals: &FxHashMap<ClientId, Decimal>,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut deposited: FxHashMap<ClientId, Decimal> = FxHashMap::default();
+    let mut disputed: FxHashMap<ClientId, Decimal> = FxHashMap::default();
+    for (tx_id, deposit) in archived_deposits {
+        *deposited.entry(deposit.client_id()).or_default() +=
+            deposit.amount() - deposit.charged_back();
+        if deposit.disputed().is_zero() {
+            continue;
+        }
+        if clients.contains_key(&deposit.client_id()) {
+            *disputed.entry(deposit.client_id()).or_default() += deposit.disputed();
+        } else {
+            violations.push(Violation {
+                client: deposit.client_id(),
+                tx: Some(*tx_id),
+                invariant: "unknown_client",
+                expected: Decimal::ZERO,
+                actual: deposit.disputed(),
+            });
+        }
+    }
+
+    for (id, client) in clients {
+        let expected_total = deposited.get(id).copied().unwrap_or_default()
+            - withdrawals.get(id).copied().unwrap_or_default();
+        if client.total != expected_total {
+            violations.push(Violation {
+                client: *id,
+                tx: None,
+                invariant: "total",
+                expected: expected_total,
+                actual: client.total,
+            });
+        }
+        let expected_held = disputed.get(id).copied().unwrap_or_default();
+        if client.held != expected_held {
+            violations.push(Violation {
+                client: *id,
+                tx: None,
+                invariant: "held",
+                expected: expected_held,
+                actual: client.held,
+            });
+        }
+    }
+    violations.sort_by_key(|violation| (violation.client, violation.tx));
+    violations
+}
+
+/// Writes the violations as csv with the columns `client,tx,invariant,expected,actual`
+pub(crate) fn write_report(
+    violations: &[Violation],
+    writer: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr =
+        csv_writer_with_header(writer, ["client", "tx", "invariant", "expected", "actual"])?;
+    for violation in violations {
+        wtr.serialize(violation)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::archived_deposit::ArchivedDeposit;
+    use crate::audit::{audit, Violation};
+    use crate::client::Client;
+    use crate::ledger::LedgerEvent;
+    use crate::{
+        ClientsDataStructure, Config, Engine, Transaction, TransactionType,
+        TransactionsDataStructure,
+    };
+    use rust_decimal::Decimal;
+    use rustc_hash::FxHashMap;
+
+    #[test]
+    fn processed_state_has_no_violations() {
+        let mut engine = Engine::new(Config::default());
+        let transactions = [
+            (TransactionType::Deposit, 1, 1, Some(10)),
+            (TransactionType::Withdrawal, 1, 2, Some(3)),
+            (TransactionType::Deposit, 1, 3, Some(4)),
+            (TransactionType::Dispute, 1, 1, Some(6)),
+            (TransactionType::Chargeback, 1, 1, None),
+            (TransactionType::Reversal, 1, 1, Some(2)),
+            (TransactionType::Deposit, 2, 4, Some(1)),
+            (TransactionType::Dispute, 2, 4, None),
+        ];
+        for (r#type, client, tx, amount) in transactions {
+            let amount = amount.map(|amount| Decimal::new(amount, 0));
+            engine
+                .process(Transaction::new(r#type, client, tx, amount))
+                .unwrap();
+        }
+        assert_eq!(engine.audit(), []);
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let mut archived_deposits = TransactionsDataStructure::default();
+        let mut deposit = ArchivedDeposit::new(1, Decimal::new(5, 0), 1);
+        deposit.dispute(1, None, 2).unwrap();
+        archived_deposits.insert(1, deposit);
+        let mut orphan = ArchivedDeposit::new(3, Decimal::new(2, 0), 3);
+        orphan.dispute(3, None, 4).unwrap();
+        archived_deposits.insert(2, orphan);
+
+        // client 1 has the deposit but neither the total nor the held funds of it
+        let mut clients = ClientsDataStructure::default();
+        let mut client = Client::new();
+        client.apply(&LedgerEvent::CreditAvailable(Decimal::new(4, 0)));
+        clients.insert(1, client);
+
+        assert_eq!(
+            audit(&clients, &archived_deposits, &FxHashMap::default()),
+            [
+                Violation {
+                    client: 1,
+                    tx: None,
+                    invariant: "total",
+                    expected: Decimal::new(5, 0),
+                    actual: Decimal::new(4, 0),
+                },
+                Violation {
+                    client: 1,
+                    tx: None,
+                    invariant: "held",
+                    expected: Decimal::new(5, 0),
+                    actual: Decimal::ZERO,
+                },
+                Violation {
+                    client: 3,
+                    tx: Some(2),
+                    invariant: "unknown_client",
+                    expected: Decimal::ZERO,
+                    actual: Decimal::new(2, 0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn balance_is_checked_apart_from_statistics() {
+        let mut archived_deposits = TransactionsDataStructure::default();
+        archived_deposits.insert(1, ArchivedDeposit::new(1, Decimal::new(10, 0), 1));
+        let mut withdrawals = FxHashMap::default();
+        withdrawals.insert(1, Decimal::new(3, 0));
+
+        // a withdrawal debited 4 instead of 3 and counted its wrong amount in the statistics as well
+        let mut clients = ClientsDataStructure::default();
+        let mut client = Client::new();
+        client.apply(&LedgerEvent::CreditAvailable(Decimal::new(10, 0)));
+        client.apply(&LedgerEvent::DebitAvailable(Decimal::new(4, 0)));
+        client.stats.deposited_volume = Decimal::new(10, 0);
+        client.stats.withdrawn_volume = Decimal::new(4, 0);
+        clients.insert(1, client);
+
+        assert_eq!(
+            audit(&clients, &archived_deposits, &withdrawals),
+            [Violation {
+                client: 1,
+                tx: None,
+                invariant: "total",
+                expected: Decimal::new(7, 0),
+                actual: Decimal::new(6, 0),
+            }]
+        );
+    }
+}
@@ -0,0 +1,195 @@
+use crate::transaction::ClientId;
+use crate::{ClientsDataStructure, Engine, TransactionsDataStructure};
+use rust_decimal::Decimal;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const INDEX_FILE: &str = "index.csv";
+
+/// When the state of the engine is written to the checkpoint directory
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckpointInterval {
+    /// after every n rows
+    Rows(u64),
+    /// whenever the value of the timestamp column changes, the input has to be ordered by timestamp
+    Timestamp,
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckpointConfig {
+    pub dir: PathBuf,
+    pub interval: CheckpointInterval,
+}
+
+/// The point in the input a query reconstructs the balances for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryTarget {
+    /// after the row with this number was processed, counted from 1
+    Row(u64),
+    /// after all rows with a timestamp up to and including this one were processed
+    Timestamp(u64),
+}
+
+impl QueryTarget {
+    /// rows without timestamp are included in timestamp queries
+    pub(crate) fn includes(&self, row: u64, timestamp: Option<u64>) -> bool {
+        match *self {
+            QueryTarget::Row(target) => row <= target,
+            QueryTarget::Timestamp(target) => timestamp.is_none_or(|timestamp| timestamp <= target),
+        }
+    }
+}
+
+/// State of all clients and archived deposits after `row` rows were processed
+#[derive(Serialize)]
+pub(crate) struct CheckpointRef<'a> {
+    pub row: u64,
+    pub timestamp: Option<u64>,
+    /// the rows are counted across these inputs
+    pub inputs: &'a [PathBuf],
+    pub clients: &'a ClientsDataStructure,
+    pub archived_deposits: &'a TransactionsDataStructure,
+    pub withdrawals: &'a FxHashMap<ClientId, Decimal>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Checkpoint {
+    pub row: u64,
+    pub inputs: Vec<PathBuf>,
+    pub clients: ClientsDataStructure,
+    pub archived_deposits: TransactionsDataStructure,
+    pub withdrawals: FxHashMap<ClientId, Decimal>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    row: u64,
+    timestamp: Option<u64>,
+    file: String,
+}
+
+/// Writes checkpoints as json files into a directory and keeps an index of them in `index.csv`
+pub(crate) struct CheckpointWriter {
+    config: CheckpointConfig,
+    inputs: Vec<PathBuf>,
+    index: csv::Writer<File>,
+    last_timestamp: Option<u64>,
+}
+
+impl CheckpointWriter {
+    pub(crate) fn create(
+        config: &CheckpointConfig,
+        inputs: &[PathBuf],
+    ) -> Result<Self, Box<dyn Error>> {
+        std::fs::create_dir_all(&config.dir)?;
+        let index = csv::Writer::from_path(config.dir.join(INDEX_FILE))?;
+
+        Ok(CheckpointWriter {
+            config: config.clone(),
+            inputs: inputs.iter().map(|input| canonical(input)).collect(),
+            index,
+            last_timestamp: None,
+        })
+    }
+
+    /// Called before a transaction is processed, writes the state of the engine if a checkpoint is due
+    pub(crate) fn before_transaction(
+        &mut self,
+        engine: &Engine,
+        timestamp: Option<u64>,
+    ) -> Result<(), Box<dyn Error>> {
+        let rows = engine.rows();
+        let due = rows > 0
+            && match self.config.interval {
+                CheckpointInterval::Rows(interval) => rows.is_multiple_of(interval),
+                CheckpointInterval::Timestamp => timestamp != self.last_timestamp,
+            };
+
+        if due {
+            self.write(engine)?;
+        }
+        self.last_timestamp = timestamp;
+        Ok(())
+    }
+
+    fn write(&mut self, engine: &Engine) -> Result<(), Box<dyn Error>> {
+        let checkpoint = engine.checkpoint(self.last_timestamp, &self.inputs);
+        let file = format!("checkpoint-{}.json", checkpoint.row);
+        let mut writer = BufWriter::new(File::create(self.config.dir.join(&file))?);
+        serde_json::to_writer(&mut writer, &checkpoint)?;
+        // a checkpoint is only listed in the index once it was written completely
+        writer.flush()?;
+
+        self.index.serialize(IndexEntry {
+            row: checkpoint.row,
+            timestamp: checkpoint.timestamp,
+            file,
+        })?;
+        // keep the index usable even if processing fails later on
+        self.index.flush()?;
+        Ok(())
+    }
+}
+
+impl Checkpoint {
+    /// Queries replay a single input from the row of the checkpoint, so the checkpoint has to be written
+    /// by a run over exactly that input
+    pub(crate) fn check_input(&self, input: &Path) -> Result<(), Box<dyn Error>> {
+        if self.inputs == [canonical(input)] {
+            return Ok(());
+        }
+        let inputs: Vec<String> = self
+            .inputs
+            .iter()
+            .map(|input| input.display().to_string())
+            .collect();
+        Err(format!(
+            "the checkpoint at row {} was written for the inputs {}, not for {}",
+            self.row,
+            inputs.join(", "),
+            input.display()
+        )
+        .into())
+    }
+}
+
+/// Absolute path of an input, so the same file given differently still matches
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Loads the latest checkpoint that lies before the target, None if there is none
+pub(crate) fn load_nearest(
+    dir: &Path,
+    target: QueryTarget,
+) -> Result<Option<Checkpoint>, Box<dyn Error>> {
+    let mut nearest: Option<IndexEntry> = None;
+    for entry in csv::Reader::from_path(dir.join(INDEX_FILE))?.deserialize() {
+        let entry: IndexEntry = entry?;
+        let before_target = match target {
+            QueryTarget::Row(row) => entry.row <= row,
+            QueryTarget::Timestamp(timestamp) => entry
+                .timestamp
+                .is_some_and(|checkpoint| checkpoint <= timestamp),
+        };
+        if before_target
+            && nearest
+                .as_ref()
+                .is_none_or(|nearest| entry.row > nearest.row)
+        {
+            nearest = Some(entry);
+        }
+    }
+
+    match nearest {
+        Some(entry) => {
+            let reader = BufReader::new(File::open(dir.join(entry.file))?);
+            Ok(Some(serde_json::from_reader(reader)?))
+        }
+        None => Ok(None),
+    }
+}
@@ -1,17 +1,46 @@
+use crate::fraud::{FraudAction, FraudFlag};
+use crate::ledger::LedgerEvent;
+use crate::transaction::ClientId;
 use rust_decimal::Decimal;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 const CLIENT: Client = Client {
     held: rust_decimal::Decimal::ZERO,
     total: rust_decimal::Decimal::ZERO,
     locked: false,
+    stats: ClientStatistics {
+        deposits: 0,
+        disputes: 0,
+        chargebacks: 0,
+        deposited_volume: rust_decimal::Decimal::ZERO,
+        withdrawn_volume: rust_decimal::Decimal::ZERO,
+        disputed_volume: rust_decimal::Decimal::ZERO,
+        charged_back_volume: rust_decimal::Decimal::ZERO,
+        reversed_volume: rust_decimal::Decimal::ZERO,
+    },
+    flag: None,
 };
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct Client {
     pub held: Decimal,
     pub total: Decimal,
     pub locked: bool,
+    pub stats: ClientStatistics,
+    pub flag: Option<FraudFlag>,
+}
+
+/// Running counts of accepted transactions, used to evaluate the fraud thresholds and for the metrics
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ClientStatistics {
+    pub deposits: u32,
+    pub disputes: u32,
+    pub chargebacks: u32,
+    pub deposited_volume: Decimal,
+    pub withdrawn_volume: Decimal,
+    pub disputed_volume: Decimal,
+    pub charged_back_volume: Decimal,
+    pub reversed_volume: Decimal,
 }
 
 impl Client {
@@ -22,25 +51,79 @@ impl Client {
     pub fn available(&self) -> Decimal {
         self.total - self.held
     }
+
+    /// Balances and lock of a client are the fold of all of its ledger events
+    pub fn apply(&mut self, event: &LedgerEvent) {
+        match *event {
+            LedgerEvent::CreditAvailable(amount) => self.total += amount,
+            LedgerEvent::DebitAvailable(amount) => self.total -= amount,
+            LedgerEvent::MoveToHeld(amount) => self.held += amount,
+            LedgerEvent::ReleaseHeld(amount) => self.held -= amount,
+            LedgerEvent::DebitHeld(amount) => {
+                self.held -= amount;
+                self.total -= amount;
+            }
+            LedgerEvent::Lock => self.locked = true,
+            LedgerEvent::Unlock => self.locked = false,
+        }
+    }
+
+    /// A locked client can be unlocked once all of its chargebacks have been reversed. Locks set by fraud detection are kept.
+    pub fn can_unlock(&self) -> bool {
+        let fraud_locked = matches!(&self.flag, Some(flag) if flag.action == FraudAction::Lock);
+        self.locked && !fraud_locked && self.stats.charged_back_volume == self.stats.reversed_volume
+    }
 }
 
+/// State of a client as it is output, `flagged` and `flag_reason` are only set when fraud detection is configured
 #[derive(Debug, Serialize)]
-pub(crate) struct ClientOutput {
-    client: u16,
-    available: Decimal,
-    held: Decimal,
-    total: Decimal,
-    locked: bool,
+pub struct ClientOutput {
+    pub client: ClientId,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+    // only written when fraud detection is configured, so the default output format stays unchanged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flagged: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flag_reason: Option<String>,
 }
 
 impl ClientOutput {
-    pub(crate) fn from_client(item: Client, id: u16) -> Self {
+    pub(crate) fn headers(with_flags: bool) -> &'static [&'static str] {
+        let headers = &[
+            "client",
+            "available",
+            "held",
+            "total",
+            "locked",
+            "flagged",
+            "flag_reason",
+        ];
+        if with_flags {
+            headers
+        } else {
+            &headers[..5]
+        }
+    }
+
+    pub(crate) fn from_client(item: &Client, id: ClientId, with_flags: bool) -> Self {
+        let (flagged, flag_reason) = if with_flags {
+            let reason = item.flag.as_ref().map(|flag| flag.reason.clone());
+            (Some(item.flag.is_some()), Some(reason.unwrap_or_default()))
+        } else {
+            (None, None)
+        };
+
         ClientOutput {
             client: id,
             available: item.total - item.held,
             held: item.held,
             total: item.total,
             locked: item.locked,
+            flagged,
+            flag_reason,
         }
     }
 }
@@ -0,0 +1,215 @@
+use crate::client::ClientOutput;
+use crate::transaction::{Transaction, TransactionType};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{ArrowPrimitiveType, Decimal128Type, UInt64Type};
+#[cfg(not(feature = "wide-ids"))]
+use arrow_array::types::{UInt16Type as ClientIdType, UInt32Type as TxIdType};
+#[cfg(feature = "wide-ids")]
+use arrow_array::types::{UInt32Type as ClientIdType, UInt64Type as TxIdType};
+use arrow_array::{
+    Array, ArrayRef, BooleanArray, Decimal128Array, PrimitiveArray, RecordBatch, StringArray,
+};
+use arrow_cast::CastOptions;
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ArrowWriter;
+use rust_decimal::Decimal;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Scale of all amounts in parquet reports, the engine works with up to four places past the decimal
+pub(crate) const AMOUNT_SCALE: i8 = 4;
+const AMOUNT_TYPE: DataType = DataType::Decimal128(38, AMOUNT_SCALE);
+
+/// Reads transactions from a parquet file batch by batch.
+/// Columns are matched by name like csv headers, `timestamp` is optional.
+pub(crate) struct ParquetTransactions {
+    batches: ParquetRecordBatchReader,
+    pending: std::vec::IntoIter<Transaction>,
+}
+
+impl ParquetTransactions {
+    pub(crate) fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?.build()?;
+        Ok(ParquetTransactions {
+            batches,
+            pending: Vec::new().into_iter(),
+        })
+    }
+
+    pub(crate) fn next(&mut self) -> Result<Option<Transaction>, Box<dyn Error>> {
+        loop {
+            if let Some(transaction) = self.pending.next() {
+                return Ok(Some(transaction));
+            }
+            match self.batches.next() {
+                Some(batch) => self.pending = transactions(&batch?)?.into_iter(),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+fn column(
+    batch: &RecordBatch,
+    name: &str,
+    data_type: &DataType,
+) -> Result<ArrayRef, Box<dyn Error>> {
+    let column = batch
+        .column_by_name(name)
+        .ok_or_else(|| format!("parquet input has no {} column", name))?;
+    // values that don't fit the type are errors instead of nulls
+    let options = CastOptions {
+        safe: false,
+        ..CastOptions::default()
+    };
+    Ok(arrow_cast::cast_with_options(column, data_type, &options)?)
+}
+
+/// A column every row needs a value of
+fn required_column(
+    batch: &RecordBatch,
+    name: &str,
+    data_type: &DataType,
+) -> Result<ArrayRef, Box<dyn Error>> {
+    let column = column(batch, name, data_type)?;
+    if column.null_count() > 0 {
+        return Err(format!("parquet input has rows without {}", name).into());
+    }
+    Ok(column)
+}
+
+fn transactions(batch: &RecordBatch) -> Result<Vec<Transaction>, Box<dyn Error>> {
+    let types = required_column(batch, "type", &DataType::Utf8)?;
+    let types = types.as_string::<i32>();
+    let clients = required_column(batch, "client", &ClientIdType::DATA_TYPE)?;
+    let clients = clients.as_primitive::<ClientIdType>();
+    let txs = required_column(batch, "tx", &TxIdType::DATA_TYPE)?;
+    let txs = txs.as_primitive::<TxIdType>();
+    // decimal columns keep their scale, anything else is converted at the scale of the engine
+    let amounts = match batch.column_by_name("amount") {
+        Some(amounts) if matches!(amounts.data_type(), DataType::Decimal128(_, _)) => {
+            amounts.clone()
+        }
+        Some(_) => column(batch, "amount", &AMOUNT_TYPE)?,
+        None => Arc::new(Decimal128Array::new_null(batch.num_rows())),
+    };
+    let scale = match amounts.data_type() {
+        DataType::Decimal128(_, scale) => *scale,
+        _ => AMOUNT_SCALE,
+    };
+    let amounts = amounts.as_primitive::<Decimal128Type>();
+    let timestamps = match batch.column_by_name("timestamp") {
+        Some(_) => Some(column(batch, "timestamp", &DataType::UInt64)?),
+        None => None,
+    };
+    let timestamps = timestamps
+        .as_ref()
+        .map(|timestamps| timestamps.as_primitive::<UInt64Type>());
+
+    let mut transactions = Vec::with_capacity(batch.num_rows());
+    for index in 0..batch.num_rows() {
+        let name: StrDeserializer<ValueError> = types.value(index).trim().into_deserializer();
+        let amount = if amounts.is_null(index) {
+            None
+        } else {
+            // trailing zeros of the fixed scale are dropped, so amounts print like amounts of csv inputs
+            Some(
+                Decimal::try_from_i128_with_scale(amounts.value(index), u32::try_from(scale)?)?
+                    .normalize(),
+            )
+        };
+
+        let mut transaction = Transaction::new(
+            TransactionType::deserialize(name)?,
+            clients.value(index),
+            txs.value(index),
+            amount,
+        );
+        transaction.timestamp = timestamps
+            .filter(|timestamps| !timestamps.is_null(index))
+            .map(|timestamps| timestamps.value(index));
+        transactions.push(transaction);
+    }
+    Ok(transactions)
+}
+
+/// Fails for amounts with more decimal places than the fixed scale instead of rounding them
+fn amounts(
+    outputs: &[ClientOutput],
+    amount: fn(&ClientOutput) -> Decimal,
+) -> Result<ArrayRef, Box<dyn Error>> {
+    let values = outputs
+        .iter()
+        .map(|output| {
+            let mut amount = amount(output);
+            if amount.normalize().scale() > AMOUNT_SCALE as u32 {
+                return Err(format!(
+                    "the amount {} of client {} has more than {} decimal places",
+                    amount, output.client, AMOUNT_SCALE
+                ));
+            }
+            amount.rescale(AMOUNT_SCALE as u32);
+            Ok(Some(amount.mantissa()))
+        })
+        .collect::<Result<Decimal128Array, _>>()?;
+    Ok(Arc::new(values.with_data_type(AMOUNT_TYPE)))
+}
+
+/// Writes the final client states as parquet with the amounts as decimal128 at scale 4
+pub(crate) fn write_report(
+    path: &Path,
+    outputs: &[ClientOutput],
+    with_flags: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut fields = vec![
+        Field::new("client", ClientIdType::DATA_TYPE, false),
+        Field::new("available", AMOUNT_TYPE, false),
+        Field::new("held", AMOUNT_TYPE, false),
+        Field::new("total", AMOUNT_TYPE, false),
+        Field::new("locked", DataType::Boolean, false),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(PrimitiveArray::<ClientIdType>::from_iter_values(
+            outputs.iter().map(|output| output.client),
+        )),
+        amounts(outputs, |output| output.available)?,
+        amounts(outputs, |output| output.held)?,
+        amounts(outputs, |output| output.total)?,
+        Arc::new(
+            outputs
+                .iter()
+                .map(|output| Some(output.locked))
+                .collect::<BooleanArray>(),
+        ),
+    ];
+    if with_flags {
+        fields.push(Field::new("flagged", DataType::Boolean, false));
+        fields.push(Field::new("flag_reason", DataType::Utf8, false));
+        columns.push(Arc::new(
+            outputs
+                .iter()
+                .map(|output| output.flagged)
+                .collect::<BooleanArray>(),
+        ));
+        columns.push(Arc::new(
+            outputs
+                .iter()
+                .map(|output| output.flag_reason.as_deref())
+                .collect::<StringArray>(),
+        ));
+    }
+
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use crate::checkpoint::CheckpointConfig;
+use crate::dialect::CsvDialect;
+use crate::fraud::FraudThresholds;
+use crate::input::{InputFormat, InputOrder};
+use crate::snapshot::SnapshotConfig;
+use crate::summary::MetricsOutput;
+use std::path::PathBuf;
+
+/// Options for a run of the engine, the defaults process the input as plain csv without any extras.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    pub fraud: FraudThresholds,
+    /// unlock clients once all of their chargebacks were reversed
+    pub unlock_on_reversal: bool,
+    /// file to export the ledger of all balance changes to, as json for `.json` files and csv otherwise
+    pub ledger: Option<PathBuf>,
+    /// file to export the double-entry journal entries of all balance changes to as csv
+    pub journal: Option<PathBuf>,
+    /// file to write the trial balance of the journal to as csv, the journal is checked against the client states
+    pub trial_balance: Option<PathBuf>,
+    /// write the state of the engine to a directory at regular intervals, so it can be queried later on
+    pub checkpoints: Option<CheckpointConfig>,
+    /// emit intermediate client states while the input is processed
+    pub snapshots: Option<SnapshotConfig>,
+    /// format of the input files, detected for every file by default
+    pub input_format: InputFormat,
+    /// delimiter, header names and value formats of csv inputs
+    pub dialect: CsvDialect,
+    /// how several input files are combined
+    pub input_order: InputOrder,
+    /// write the final client states to this parquet file instead of csv to stdout
+    pub parquet_report: Option<PathBuf>,
+    /// print statistics of every input file to stderr
+    pub summary: bool,
+    /// counts, volumes and throughput of the whole run
+    pub metrics: Option<MetricsOutput>,
+    /// verify global invariants of the client states and archived deposits after processing
+    pub audit: bool,
+}
@@ -0,0 +1,170 @@
+use csv_async::{AsyncReaderBuilder, ByteRecord};
+
+/// Columns in the order they are expected in csv inputs without a header row
+const DEFAULT_COLUMNS: &[&str] = &["type", "client", "tx", "amount", "timestamp"];
+
+/// How csv inputs are written, the defaults read comma separated files with a `type,client,tx,amount` header
+#[derive(Debug, Clone)]
+pub struct CsvDialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    /// without a header row the columns are expected in the order type, client, tx, amount, timestamp
+    pub has_headers: bool,
+    /// header names of the input mapped to the names the engine expects, e.g. `client_id` to `client`
+    pub columns: Vec<(String, String)>,
+    /// accept transaction types in any case, e.g. `DEPOSIT` or `Deposit`
+    pub case_insensitive_types: bool,
+    /// amounts use a comma as decimal separator, e.g. `1,5`. Requires another delimiter or quoted amounts.
+    pub decimal_comma: bool,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        CsvDialect {
+            delimiter: b',',
+            quote: b'"',
+            has_headers: true,
+            columns: Vec::new(),
+            case_insensitive_types: false,
+            decimal_comma: false,
+        }
+    }
+}
+
+impl CsvDialect {
+    pub(crate) fn reader_builder(&self) -> AsyncReaderBuilder {
+        let mut builder = AsyncReaderBuilder::new();
+        builder
+            .trim(csv_async::Trim::All)
+            .flexible(true)
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .has_headers(self.has_headers);
+        builder
+    }
+
+    /// Renames the headers of the input to the names the engine expects,
+    /// or returns the default columns for inputs without a header row
+    pub(crate) fn map_headers(&self, headers: &ByteRecord) -> ByteRecord {
+        if !self.has_headers {
+            return ByteRecord::from(DEFAULT_COLUMNS.to_vec());
+        }
+
+        headers
+            .iter()
+            .map(|header| {
+                self.columns
+                    .iter()
+                    .find(|(from, _)| from.as_bytes() == header)
+                    .map_or(header, |(_, to)| to.as_bytes())
+            })
+            .collect()
+    }
+
+    /// Rewrites the fields that differ from the default dialect, None if records can be deserialized as they are
+    pub(crate) fn normalizer(&self, headers: &ByteRecord) -> Option<RecordNormalizer> {
+        if !self.case_insensitive_types && !self.decimal_comma {
+            return None;
+        }
+
+        let position = |name: &str| headers.iter().position(|header| header == name.as_bytes());
+        Some(RecordNormalizer {
+            type_column: position("type").filter(|_| self.case_insensitive_types),
+            amount_column: position("amount").filter(|_| self.decimal_comma),
+            record: ByteRecord::new(),
+        })
+    }
+}
+
+pub(crate) struct RecordNormalizer {
+    /// lowercased if set
+    type_column: Option<usize>,
+    /// decimal comma replaced by a point if set
+    amount_column: Option<usize>,
+    record: ByteRecord,
+}
+
+impl RecordNormalizer {
+    pub(crate) fn normalize(&mut self, raw_record: &ByteRecord) -> &ByteRecord {
+        self.record.clear();
+        for (index, field) in raw_record.iter().enumerate() {
+            if Some(index) == self.type_column {
+                self.record.push_field(&field.to_ascii_lowercase());
+            } else if Some(index) == self.amount_column {
+                let field: Vec<u8> = field
+                    .iter()
+                    .map(|&byte| if byte == b',' { b'.' } else { byte })
+                    .collect();
+                self.record.push_field(&field);
+            } else {
+                self.record.push_field(field);
+            }
+        }
+        &self.record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dialect::CsvDialect;
+    use csv_async::ByteRecord;
+
+    fn partner_dialect() -> CsvDialect {
+        CsvDialect {
+            delimiter: b';',
+            columns: vec![
+                ("client_id".to_string(), "client".to_string()),
+                ("txid".to_string(), "tx".to_string()),
+            ],
+            case_insensitive_types: true,
+            decimal_comma: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn map_header_names() {
+        let headers = ByteRecord::from(vec!["type", "client_id", "txid", "amount"]);
+        assert_eq!(
+            partner_dialect().map_headers(&headers),
+            ByteRecord::from(vec!["type", "client", "tx", "amount"])
+        );
+    }
+
+    #[test]
+    fn default_columns_without_headers() {
+        let dialect = CsvDialect {
+            has_headers: false,
+            ..Default::default()
+        };
+        let headers = ByteRecord::from(vec!["deposit", "1", "1", "1.0"]);
+        assert_eq!(
+            dialect.map_headers(&headers),
+            ByteRecord::from(vec!["type", "client", "tx", "amount", "timestamp"])
+        );
+    }
+
+    #[test]
+    fn default_dialect_keeps_records() {
+        let headers = ByteRecord::from(vec!["type", "client", "tx", "amount"]);
+        assert!(CsvDialect::default().normalizer(&headers).is_none());
+    }
+
+    #[test]
+    fn normalize_type_and_decimal_comma() {
+        let dialect = partner_dialect();
+        let headers = dialect.map_headers(&ByteRecord::from(vec![
+            "type",
+            "client_id",
+            "txid",
+            "amount",
+        ]));
+        let mut normalizer = dialect.normalizer(&headers).unwrap();
+
+        let record = ByteRecord::from(vec!["DEPOSIT", "1", "2", "1,5"]);
+        assert_eq!(
+            normalizer.normalize(&record),
+            &ByteRecord::from(vec!["deposit", "1", "2", "1.5"])
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::transaction::ClientId;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A client report loaded by column name, rows are keyed by client id
+pub(crate) struct Report {
+    headers: Vec<String>,
+    rows: BTreeMap<ClientId, Vec<String>>,
+}
+
+/// One difference between two reports
+#[derive(Debug, PartialEq, Serialize)]
+pub(crate) struct Difference {
+    client: Option<ClientId>,
+    /// missing_in_expected, missing_in_actual or mismatch
+    problem: &'static str,
+    field: Option<String>,
+    expected: Option<String>,
+    actual: Option<String>,
+}
+
+impl Report {
+    pub(crate) fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut rdr = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)?;
+        let headers: Vec<String> = rdr.headers()?.iter().map(String::from).collect();
+        let client_column = headers
+            .iter()
+            .position(|header| header == "client")
+            .ok_or_else(|| format!("{} has no client column", path.display()))?;
+
+        let mut rows = BTreeMap::new();
+        for record in rdr.records() {
+            let record = record?;
+            let client = record.get(client_column).unwrap_or_default();
+            let client: ClientId = client
+                .parse()
+                .map_err(|_| format!("invalid client id {} in {}", client, path.display()))?;
+            let values = record.iter().map(String::from).collect();
+            if rows.insert(client, values).is_some() {
+                return Err(
+                    format!("client {} appears twice in {}", client, path.display()).into(),
+                );
+            }
+        }
+        Ok(Report { headers, rows })
+    }
+
+    fn value(&self, row: &[String], field: &str) -> Option<String> {
+        let column = self.headers.iter().position(|header| header == field)?;
+        row.get(column).cloned()
+    }
+}
+
+/// Values are equal if both are the same decimal, regardless of their scale, or the same text
+fn values_equal(expected: &str, actual: &str) -> bool {
+    match (Decimal::from_str(expected), Decimal::from_str(actual)) {
+        (Ok(expected), Ok(actual)) => expected == actual,
+        _ => expected == actual,
+    }
+}
+
+/// Compares all columns of clients in both reports and lists the clients missing from either, ordered by client id
+pub(crate) fn differences(expected: &Report, actual: &Report) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    let fields: Vec<&String> = expected
+        .headers
+        .iter()
+        .filter(|header| *header != "client")
+        .collect();
+    for field in &fields {
+        if !actual.headers.contains(field) {
+            differences.push(Difference {
+                client: None,
+                problem: "missing_in_actual",
+                field: Some(field.to_string()),
+                expected: None,
+                actual: None,
+            });
+        }
+    }
+    for field in &actual.headers {
+        if !expected.headers.contains(field) {
+            differences.push(Difference {
+                client: None,
+                problem: "missing_in_expected",
+                field: Some(field.clone()),
+                expected: None,
+                actual: None,
+            });
+        }
+    }
+
+    let mut clients: Vec<&ClientId> = expected.rows.keys().chain(actual.rows.keys()).collect();
+    clients.sort_unstable();
+    clients.dedup();
+    for client in clients {
+        match (expected.rows.get(client), actual.rows.get(client)) {
+            (Some(expected_row), Some(actual_row)) => {
+                for field in &fields {
+                    let expected_value = expected.value(expected_row, field);
+                    let actual_value = match actual.value(actual_row, field) {
+                        Some(value) => value,
+                        // reported once for the whole column
+                        None => continue,
+                    };
+                    let expected_value = expected_value.unwrap_or_default();
+                    if !values_equal(&expected_value, &actual_value) {
+                        differences.push(Difference {
+                            client: Some(*client),
+                            problem: "mismatch",
+                            field: Some(field.to_string()),
+                            expected: Some(expected_value),
+                            actual: Some(actual_value),
+                        });
+                    }
+                }
+            }
+            (expected_row, _) => differences.push(Difference {
+                client: Some(*client),
+                problem: if expected_row.is_some() {
+                    "missing_in_actual"
+                } else {
+                    "missing_in_expected"
+                },
+                field: None,
+                expected: None,
+                actual: None,
+            }),
+        }
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{differences, values_equal, Report};
+    use crate::transaction::ClientId;
+    use std::collections::BTreeMap;
+
+    fn report(headers: &[&str], rows: &[&[&str]]) -> Report {
+        let mut map = BTreeMap::new();
+        for row in rows {
+            map.insert(
+                row[0].parse().unwrap(),
+                row.iter().map(|value| value.to_string()).collect(),
+            );
+        }
+        Report {
+            headers: headers.iter().map(|header| header.to_string()).collect(),
+            rows: map,
+        }
+    }
+
+    #[test]
+    fn decimals_equal_regardless_of_scale() {
+        assert!(values_equal("2", "2.0000"));
+        assert!(values_equal("-1.5", "-1.50"));
+        assert!(!values_equal("1.0001", "1"));
+        assert!(values_equal("true", "true"));
+        assert!(!values_equal("true", "false"));
+    }
+
+    #[test]
+    fn report_missing_clients_and_mismatches() {
+        let headers = ["client", "available", "locked"];
+        let expected = report(&headers, &[&["1", "1.5", "false"], &["2", "2", "false"]]);
+        let actual = report(&headers, &[&["3", "0", "false"], &["1", "1.50", "true"]]);
+
+        let differences = differences(&expected, &actual);
+        let summary: Vec<(Option<ClientId>, &str, Option<&str>)> = differences
+            .iter()
+            .map(|difference| {
+                (
+                    difference.client,
+                    difference.problem,
+                    difference.field.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Some(1), "mismatch", Some("locked")),
+                (Some(2), "missing_in_actual", None),
+                (Some(3), "missing_in_expected", None),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_missing_columns_once() {
+        let expected = report(&["client", "available", "flagged"], &[&["1", "1", "false"]]);
+        let actual = report(&["client", "available"], &[&["1", "1"]]);
+
+        let differences = differences(&expected, &actual);
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].problem, "missing_in_actual");
+        assert_eq!(differences[0].field.as_deref(), Some("flagged"));
+    }
+}
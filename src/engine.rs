@@ -0,0 +1,229 @@
+use crate::audit::{self, Violation};
+use crate::checkpoint::{Checkpoint, CheckpointRef};
+use crate::client::{Client, ClientOutput};
+use crate::execute_transaction::{emit, execute_transaction, trace_outcome};
+use crate::journal::{Journal, JournalEntry};
+use crate::ledger::{LedgerEntry, LedgerEvent};
+use crate::rejection::Rejection;
+use crate::transaction::{ClientId, Transaction, TransactionType, TxId};
+use crate::{ArchivedDeposit, ClientsDataStructure, Config, TransactionsDataStructure};
+use rust_decimal::Decimal;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::path::PathBuf;
+
+/// Holds the state of all clients and archived deposits while transactions are processed one by one
+pub struct Engine {
+    config: Config,
+    clients: ClientsDataStructure,
+    archived_deposits: TransactionsDataStructure,
+    /// sum of the accepted withdrawals per client, counted apart from the balances for the audit
+    withdrawals: FxHashMap<ClientId, Decimal>,
+    rows: u64,
+    /// entries that were not yet taken by `drain_ledger`, only recorded when a ledger is configured
+    ledger: Vec<LedgerEntry>,
+    record_ledger: bool,
+    /// balances of the double-entry accounts, only kept when a journal or trial balance is configured
+    journal: Option<Journal>,
+    /// clients that changed since the last `drain_dirty`, only tracked when snapshots are configured
+    dirty: FxHashSet<ClientId>,
+}
+
+impl Engine {
+    pub fn new(config: Config) -> Self {
+        Engine {
+            record_ledger: config.ledger.is_some(),
+            journal: (config.journal.is_some() || config.trial_balance.is_some())
+                .then(|| Journal::new(config.journal.is_some())),
+            config,
+            clients: ClientsDataStructure::default(),
+            archived_deposits: TransactionsDataStructure::default(),
+            withdrawals: FxHashMap::default(),
+            rows: 0,
+            ledger: Vec::new(),
+            dirty: FxHashSet::default(),
+        }
+    }
+
+    /// Applies the transaction to its client, transactions of locked clients are ignored.
+    /// Returns the reason if the transaction was ignored and left the state of the client unchanged.
+    pub fn process(&mut self, mut transaction: Transaction) -> Result<(), Rejection> {
+        self.rows += 1;
+        transaction.row = self.rows;
+
+        let config = &self.config;
+        let archived_deposits = &mut self.archived_deposits;
+        let dirty = &mut self.dirty;
+        let track_dirty = config.snapshots.is_some();
+        let client = self
+            .clients
+            .entry(transaction.client_id)
+            .or_insert_with(|| {
+                if track_dirty {
+                    dirty.insert(transaction.client_id);
+                }
+                Client::new()
+            });
+
+        // reversals are the only transactions accepted for locked clients
+        if client.locked && transaction.r#type != TransactionType::Reversal {
+            trace_outcome(&transaction, Err(&Rejection::ClientLocked));
+            return Err(Rejection::ClientLocked);
+        }
+
+        let mut effects = execute_transaction(&transaction, client, archived_deposits)?;
+        if transaction.r#type == TransactionType::Withdrawal {
+            *self.withdrawals.entry(transaction.client_id).or_default() +=
+                transaction.amount.unwrap_or_default();
+        }
+        if config.unlock_on_reversal
+            && transaction.r#type == TransactionType::Reversal
+            && client.can_unlock()
+        {
+            emit(&transaction, client, &mut effects, LedgerEvent::Unlock);
+        }
+        if config.fraud.evaluate(client) && !client.locked {
+            emit(&transaction, client, &mut effects, LedgerEvent::Lock);
+        }
+
+        if track_dirty {
+            dirty.insert(transaction.client_id);
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.post(transaction.r#type, &effects);
+        }
+        if self.record_ledger {
+            self.ledger.extend(effects);
+        }
+        Ok(())
+    }
+
+    /// Records ledger entries for `drain_ledger` without a ledger file configured
+    pub fn with_ledger(mut self) -> Self {
+        self.record_ledger = true;
+        self
+    }
+
+    /// Takes the ledger entries recorded since the last call
+    pub fn drain_ledger(&mut self) -> impl Iterator<Item = LedgerEntry> + '_ {
+        self.ledger.drain(..)
+    }
+
+    /// Keeps a double-entry journal and records its entries for `drain_journal`
+    pub fn with_journal(mut self) -> Self {
+        self.journal = Some(Journal::new(true));
+        self
+    }
+
+    /// Takes the journal entries recorded since the last call, nothing without a journal
+    pub fn drain_journal(&mut self) -> impl Iterator<Item = JournalEntry> + '_ {
+        self.journal.iter_mut().flat_map(|journal| journal.drain())
+    }
+
+    /// Invariants of the journal that don't hold for the current client states, empty without a journal
+    pub fn journal_violations(&self) -> Vec<String> {
+        self.journal
+            .as_ref()
+            .map(|journal| journal.check(&self.clients))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Global invariants that don't hold for the current client states and archived deposits
+    pub(crate) fn audit(&self) -> Vec<Violation> {
+        audit::audit(&self.clients, &self.archived_deposits, &self.withdrawals)
+    }
+
+    pub fn deposit(&self, tx_id: TxId) -> Option<&ArchivedDeposit> {
+        self.archived_deposits.get(&tx_id)
+    }
+
+    /// All archived deposits, e.g. to query the ones in a certain state
+    pub fn deposits(&self) -> impl Iterator<Item = (TxId, &ArchivedDeposit)> {
+        self.archived_deposits
+            .iter()
+            .map(|(tx_id, deposit)| (*tx_id, deposit))
+    }
+
+    /// Number of rows processed so far
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    pub(crate) fn into_outputs(self) -> impl Iterator<Item = ClientOutput> {
+        let with_flags = self.config.fraud.is_enabled();
+        self.clients
+            .into_iter()
+            .map(move |(id, client)| ClientOutput::from_client(&client, id, with_flags))
+    }
+
+    /// Current state of all clients, in no particular order
+    pub fn client_outputs(&self) -> impl Iterator<Item = ClientOutput> + '_ {
+        let with_flags = self.config.fraud.is_enabled();
+        self.clients
+            .iter()
+            .map(move |(id, client)| ClientOutput::from_client(client, *id, with_flags))
+    }
+
+    pub(crate) fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub(crate) fn clients(&self) -> impl Iterator<Item = &Client> {
+        self.clients.values()
+    }
+
+    /// Estimated memory of the archived deposits. Deposits are never removed, so this is also the peak.
+    pub(crate) fn archive_bytes(&self) -> usize {
+        // every bucket of the hash map holds a key, a value and a control byte
+        self.archived_deposits.capacity()
+            * (std::mem::size_of::<TxId>() + std::mem::size_of::<ArchivedDeposit>() + 1)
+    }
+
+    /// Takes the ids of all clients that changed since the last call, ordered by id
+    pub(crate) fn drain_dirty(&mut self) -> Vec<ClientId> {
+        let mut dirty: Vec<ClientId> = self.dirty.drain().collect();
+        dirty.sort_unstable();
+        dirty
+    }
+
+    pub(crate) fn client_output(&self, client_id: ClientId) -> Option<ClientOutput> {
+        let with_flags = self.config.fraud.is_enabled();
+        self.clients
+            .get(&client_id)
+            .map(|client| ClientOutput::from_client(client, client_id, with_flags))
+    }
+
+    pub(crate) fn checkpoint<'a>(
+        &'a self,
+        timestamp: Option<u64>,
+        inputs: &'a [PathBuf],
+    ) -> CheckpointRef<'a> {
+        CheckpointRef {
+            row: self.rows,
+            timestamp,
+            inputs,
+            clients: &self.clients,
+            archived_deposits: &self.archived_deposits,
+            withdrawals: &self.withdrawals,
+        }
+    }
+
+    /// Continues processing from a checkpoint, the next row processed is the one after the checkpoint
+    pub(crate) fn from_checkpoint(config: Config, checkpoint: Checkpoint) -> Self {
+        Engine {
+            record_ledger: config.ledger.is_some(),
+            config,
+            clients: checkpoint.clients,
+            archived_deposits: checkpoint.archived_deposits,
+            withdrawals: checkpoint.withdrawals,
+            rows: checkpoint.row,
+            ledger: Vec::new(),
+            // the journal only covers transactions processed by this engine
+            journal: None,
+            dirty: FxHashSet::default(),
+        }
+    }
+}
@@ -1,63 +1,194 @@
+use crate::ledger::{Effects, LedgerEntry, LedgerEvent};
+use crate::rejection::Rejection;
 use crate::{
     transaction::TransactionType, ArchivedDeposit, Client, Transaction, TransactionsDataStructure,
 };
+use rust_decimal::Decimal;
 
+/// Largest amount of a deposit or withdrawal, 10^15, far below the limit of Decimal so balances can't overflow
+pub(crate) const MAX_AMOUNT: Decimal = Decimal::from_parts(0xA4C6_8000, 0x3_8D7E, 0, false, 0);
+
+/// Applies the transaction to the client and returns the ledger entries of all effects it had,
+/// or the reason it was ignored
 pub(crate) fn execute_transaction(
     transaction: &Transaction,
     client: &mut Client,
     archived_deposits: &mut TransactionsDataStructure,
-) {
+) -> Result<Effects, Rejection> {
+    let result = apply_transaction(transaction, client, archived_deposits);
+    trace_outcome(transaction, result.as_ref().map(|_| ()));
+    result
+}
+
+/// Debug event with the outcome of a processed transaction, `applied` or the category of the rejection
+pub(crate) fn trace_outcome(transaction: &Transaction, result: Result<(), &Rejection>) {
+    tracing::debug!(
+        client = transaction.client_id,
+        tx = transaction.tx_id,
+        r#type = transaction.r#type.name(),
+        outcome = match result {
+            Ok(()) => "applied",
+            Err(rejection) => rejection.category(),
+        },
+        "transaction processed"
+    );
+}
+
+fn apply_transaction(
+    transaction: &Transaction,
+    client: &mut Client,
+    archived_deposits: &mut TransactionsDataStructure,
+) -> Result<Effects, Rejection> {
+    let mut effects = Effects::new();
     match transaction.r#type {
         TransactionType::Deposit => {
-            let amount = transaction.amount.unwrap();
-            if amount.is_sign_positive() {
-                client.total += amount;
-                archived_deposits.insert(transaction.tx_id, ArchivedDeposit::new(amount));
+            let amount = positive_amount(transaction)?;
+            // replacing the archived deposit would strand its disputed or charged back funds
+            if archived_deposits.contains_key(&transaction.tx_id) {
+                return Err(Rejection::DuplicateTx);
             }
+            emit(
+                transaction,
+                client,
+                &mut effects,
+                LedgerEvent::CreditAvailable(amount),
+            );
+            client.stats.deposits += 1;
+            client.stats.deposited_volume += amount;
+            archived_deposits.insert(
+                transaction.tx_id,
+                ArchivedDeposit::new(transaction.client_id, amount, transaction.row),
+            );
         }
         TransactionType::Withdrawal => {
-            let amount = transaction.amount.unwrap();
-            if amount.is_sign_positive() && client.available() >= amount {
-                client.total -= amount;
+            let amount = positive_amount(transaction)?;
+            if client.available() < amount {
+                return Err(Rejection::InsufficientFunds);
             }
+            emit(
+                transaction,
+                client,
+                &mut effects,
+                LedgerEvent::DebitAvailable(amount),
+            );
+            client.stats.withdrawn_volume += amount;
         }
+        // the referenced deposit validates the transition
         TransactionType::Dispute => {
-            if let Some(referenced_transaction) = archived_deposits.get_mut(&transaction.tx_id) {
-                // don't allow two disputes, otherwise the held amount would be too high
-                if !referenced_transaction.disputed {
-                    client.held += referenced_transaction.amount;
-                    referenced_transaction.disputed = true;
-                }
-            }
+            let amount = referenced_deposit(transaction, archived_deposits)?.dispute(
+                transaction.client_id,
+                transaction.amount,
+                transaction.row,
+            )?;
+            emit(
+                transaction,
+                client,
+                &mut effects,
+                LedgerEvent::MoveToHeld(amount),
+            );
+            client.stats.disputes += 1;
+            client.stats.disputed_volume += amount;
         }
         TransactionType::Resolve => {
-            if let Some(referenced_transaction) = archived_deposits.get_mut(&transaction.tx_id) {
-                if referenced_transaction.disputed {
-                    client.held -= referenced_transaction.amount;
-                    referenced_transaction.disputed = false;
-                }
-            }
+            let amount = referenced_deposit(transaction, archived_deposits)?.resolve(
+                transaction.client_id,
+                transaction.amount,
+                transaction.row,
+            )?;
+            emit(
+                transaction,
+                client,
+                &mut effects,
+                LedgerEvent::ReleaseHeld(amount),
+            );
         }
         TransactionType::Chargeback => {
-            if let Some(referenced_transaction) = archived_deposits.get(&transaction.tx_id) {
-                if referenced_transaction.disputed {
-                    client.held -= referenced_transaction.amount;
-                    client.total -= referenced_transaction.amount;
-                    client.locked = true;
-                    // Remove transaction otherwise it could be resolved again even though funds were returned
-                    archived_deposits.remove(&transaction.tx_id);
-                }
+            // the deposit is kept so the chargeback can be reversed, its state prevents it from being resolved again
+            let amount = referenced_deposit(transaction, archived_deposits)?.charge_back(
+                transaction.client_id,
+                transaction.amount,
+                transaction.row,
+            )?;
+            emit(
+                transaction,
+                client,
+                &mut effects,
+                LedgerEvent::DebitHeld(amount),
+            );
+            if !client.locked {
+                emit(transaction, client, &mut effects, LedgerEvent::Lock);
             }
+            client.stats.chargebacks += 1;
+            client.stats.charged_back_volume += amount;
+        }
+        TransactionType::Reversal => {
+            let amount = referenced_deposit(transaction, archived_deposits)?.reverse(
+                transaction.client_id,
+                transaction.amount,
+                transaction.row,
+            )?;
+            emit(
+                transaction,
+                client,
+                &mut effects,
+                LedgerEvent::CreditAvailable(amount),
+            );
+            client.stats.reversed_volume += amount;
         }
     }
+    Ok(effects)
+}
+
+/// amount of a deposit or withdrawal, zero is accepted
+fn positive_amount(transaction: &Transaction) -> Result<Decimal, Rejection> {
+    let amount = transaction.amount.ok_or(Rejection::MissingAmount)?;
+    if !amount.is_sign_positive() {
+        Err(Rejection::NegativeAmount)
+    } else if amount > MAX_AMOUNT {
+        Err(Rejection::AmountTooLarge)
+    } else {
+        Ok(amount)
+    }
+}
+
+fn referenced_deposit<'a>(
+    transaction: &Transaction,
+    archived_deposits: &'a mut TransactionsDataStructure,
+) -> Result<&'a mut ArchivedDeposit, Rejection> {
+    archived_deposits
+        .get_mut(&transaction.tx_id)
+        .ok_or(Rejection::UnknownDeposit)
+}
+
+/// Applies the event to the client and records it together with the resulting balances
+pub(crate) fn emit(
+    transaction: &Transaction,
+    client: &mut Client,
+    effects: &mut Effects,
+    event: LedgerEvent,
+) {
+    client.apply(&event);
+    effects.push(LedgerEntry {
+        row: transaction.row,
+        client_id: transaction.client_id,
+        tx_id: transaction.tx_id,
+        event,
+        available: client.available(),
+        held: client.held,
+        total: client.total,
+        locked: client.locked,
+    });
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::execute_transaction::execute_transaction;
+    use crate::archived_deposit::{DepositState, TransitionError};
+    use crate::execute_transaction::{execute_transaction, MAX_AMOUNT};
+    use crate::ledger::LedgerEvent;
+    use crate::rejection::Rejection;
     use crate::{
         transaction::TransactionType, ArchivedDeposit, Client, Transaction,
-        TransactionsDataStructure,
+        TransactionsDataStructure, TxId,
     };
     use rust_decimal::Decimal;
     use std::ops::{Add, Neg, Sub};
@@ -81,27 +212,40 @@ mod tests {
             assert_eq!(self.locked, locked);
             self
         }
+
+        pub fn assert_stats(&self, deposits: u32, disputes: u32, chargebacks: u32) -> &Client {
+            assert_eq!(self.stats.deposits, deposits);
+            assert_eq!(self.stats.disputes, disputes);
+            assert_eq!(self.stats.chargebacks, chargebacks);
+            self
+        }
     }
 
     trait ArchiveTransactionAssertions {
-        fn assert_amount(self, tx: u32, amount: Decimal) -> TransactionsDataStructure;
-        fn assert_disputed(self, tx: u32, disputed: bool) -> TransactionsDataStructure;
-        fn assert_removed(self, tx: u32) -> TransactionsDataStructure;
+        fn assert_amount(self, tx: TxId, amount: Decimal) -> TransactionsDataStructure;
+        fn assert_disputed(self, tx: TxId, disputed: bool) -> TransactionsDataStructure;
+        fn assert_disputed_amount(self, tx: TxId, amount: Decimal) -> TransactionsDataStructure;
+        fn assert_state(self, tx: TxId, state: DepositState) -> TransactionsDataStructure;
     }
 
     impl ArchiveTransactionAssertions for TransactionsDataStructure {
-        fn assert_amount(self, tx: u32, amount: Decimal) -> TransactionsDataStructure {
+        fn assert_amount(self, tx: TxId, amount: Decimal) -> TransactionsDataStructure {
             assert_eq!(self.get(&tx).unwrap().amount, amount);
             self
         }
 
-        fn assert_disputed(self, tx: u32, disputed: bool) -> TransactionsDataStructure {
-            assert_eq!(self.get(&tx).unwrap().disputed, disputed);
+        fn assert_disputed(self, tx: TxId, disputed: bool) -> TransactionsDataStructure {
+            assert_eq!(!self.get(&tx).unwrap().disputed.is_zero(), disputed);
             self
         }
 
-        fn assert_removed(self, tx: u32) -> TransactionsDataStructure {
-            assert_eq!(self.get(&tx).is_none(), true);
+        fn assert_disputed_amount(self, tx: TxId, amount: Decimal) -> TransactionsDataStructure {
+            assert_eq!(self.get(&tx).unwrap().disputed, amount);
+            self
+        }
+
+        fn assert_state(self, tx: TxId, state: DepositState) -> TransactionsDataStructure {
+            assert_eq!(self.get(&tx).unwrap().state, state);
             self
         }
     }
@@ -111,8 +255,10 @@ mod tests {
             Transaction {
                 r#type: TransactionType::Deposit,
                 client_id: 1,
-                tx_id: 3,
+                tx_id: 4,
                 amount: Some(amount),
+                timestamp: None,
+                row: 0,
             }
         }
 
@@ -122,35 +268,60 @@ mod tests {
                 client_id: 1,
                 tx_id: 3,
                 amount: Some(amount),
+                timestamp: None,
+                row: 0,
             }
         }
 
-        pub fn new_dispute(tx: u32) -> Transaction {
+        pub fn new_dispute(tx: TxId) -> Transaction {
             Transaction {
                 r#type: TransactionType::Dispute,
                 client_id: 1,
                 tx_id: tx,
                 amount: None,
+                timestamp: None,
+                row: 0,
             }
         }
 
-        pub fn new_resolve(tx: u32) -> Transaction {
+        pub fn new_resolve(tx: TxId) -> Transaction {
             Transaction {
                 r#type: TransactionType::Resolve,
                 client_id: 1,
                 tx_id: tx,
                 amount: None,
+                timestamp: None,
+                row: 0,
             }
         }
 
-        pub fn new_chargeback(tx: u32) -> Transaction {
+        pub fn new_chargeback(tx: TxId) -> Transaction {
             Transaction {
                 r#type: TransactionType::Chargeback,
                 client_id: 1,
                 tx_id: tx,
                 amount: None,
+                timestamp: None,
+                row: 0,
             }
         }
+
+        pub fn new_reversal(tx: TxId) -> Transaction {
+            Transaction {
+                r#type: TransactionType::Reversal,
+                client_id: 1,
+                tx_id: tx,
+                amount: None,
+                timestamp: None,
+                row: 0,
+            }
+        }
+
+        /// turns a dispute, resolve or chargeback into a partial one
+        pub fn with_amount(mut self, amount: Decimal) -> Transaction {
+            self.amount = Some(amount);
+            self
+        }
     }
 
     /// clients: id:1, available: 3000.8114, held: 0, total: 3000.8114, locked:false
@@ -158,14 +329,15 @@ mod tests {
     fn initial_state() -> (Client, TransactionsDataStructure) {
         let mut archived_transactions = TransactionsDataStructure::default();
 
-        archived_transactions.insert(2, ArchivedDeposit::new(Decimal::new(5006914, 4)));
-        archived_transactions.insert(1, ArchivedDeposit::new(Decimal::new(150012, 2)));
-        archived_transactions.insert(3, ArchivedDeposit::new(Decimal::new(1000, 0)));
+        archived_transactions.insert(2, ArchivedDeposit::new(1, Decimal::new(5006914, 4), 0));
+        archived_transactions.insert(1, ArchivedDeposit::new(1, Decimal::new(150012, 2), 0));
+        archived_transactions.insert(3, ArchivedDeposit::new(1, Decimal::new(1000, 0), 0));
 
         let client = Client {
             held: Default::default(),
             total: initial_amount(),
             locked: false,
+            ..Client::new()
         };
 
         (client, archived_transactions)
@@ -186,7 +358,7 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_deposit(Decimal::default());
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount())
@@ -201,17 +373,73 @@ mod tests {
 
         let amount = thousand();
         let deposit = Transaction::new_deposit(amount);
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount().add(amount))
             .assert_held(Decimal::default())
             .assert_available(initial_amount().add(amount))
-            .assert_frozen(false);
+            .assert_frozen(false)
+            .assert_stats(1, 0, 0);
 
         archived_transactions
-            .assert_amount(3, amount)
-            .assert_disputed(3, false);
+            .assert_amount(4, amount)
+            .assert_disputed(4, false);
+    }
+
+    #[test]
+    fn reject_deposit_with_archived_tx_id() {
+        let (mut client, mut archived_transactions) = initial_state();
+        let dispute = Transaction::new_dispute(3);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        let mut deposit = Transaction::new_deposit(Decimal::new(5, 0));
+        deposit.tx_id = 3;
+        deposit.client_id = 2;
+        assert_eq!(
+            execute_transaction(&deposit, &mut client, &mut archived_transactions),
+            Err(Rejection::DuplicateTx)
+        );
+
+        client
+            .assert_total(initial_amount())
+            .assert_held(thousand());
+        assert_eq!(archived_transactions.get(&3).unwrap().client_id(), 1);
+        archived_transactions
+            .assert_amount(3, thousand())
+            .assert_disputed(3, true);
+    }
+
+    #[test]
+    fn reject_deposit_without_amount() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 4, None);
+        assert_eq!(
+            execute_transaction(&deposit, &mut client, &mut archived_transactions),
+            Err(Rejection::MissingAmount)
+        );
+
+        client.assert_total(initial_amount()).assert_stats(0, 0, 0);
+        assert!(!archived_transactions.contains_key(&4));
+    }
+
+    #[test]
+    fn reject_deposit_above_max_amount() {
+        assert_eq!(MAX_AMOUNT, Decimal::new(1_000_000_000_000_000, 0));
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 4, Some(Decimal::MAX));
+        assert_eq!(
+            execute_transaction(&deposit, &mut client, &mut archived_transactions),
+            Err(Rejection::AmountTooLarge)
+        );
+        let deposit = Transaction::new(TransactionType::Deposit, 1, 4, Some(MAX_AMOUNT));
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_total(initial_amount() + MAX_AMOUNT)
+            .assert_stats(1, 0, 0);
     }
 
     #[test]
@@ -219,7 +447,10 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_deposit(thousand().neg());
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&deposit, &mut client, &mut archived_transactions),
+            Err(Rejection::NegativeAmount)
+        );
 
         client
             .assert_total(initial_amount())
@@ -233,7 +464,7 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_withdrawal(Decimal::default());
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount())
@@ -247,7 +478,7 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_withdrawal(initial_amount());
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(Decimal::default())
@@ -261,7 +492,10 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_withdrawal(initial_amount() + thousand());
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&deposit, &mut client, &mut archived_transactions),
+            Err(Rejection::InsufficientFunds)
+        );
 
         client
             .assert_total(initial_amount())
@@ -276,7 +510,7 @@ mod tests {
 
         let amount = thousand();
         let deposit = Transaction::new_withdrawal(amount);
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount().sub(amount))
@@ -290,7 +524,10 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_withdrawal(thousand().neg());
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&deposit, &mut client, &mut archived_transactions),
+            Err(Rejection::NegativeAmount)
+        );
 
         client
             .assert_total(initial_amount())
@@ -304,7 +541,7 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_dispute(3);
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount())
@@ -318,7 +555,7 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let dispute = Transaction::new_dispute(3);
-        execute_transaction(&dispute, &mut client, &mut archived_transactions);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount())
@@ -327,13 +564,18 @@ mod tests {
             .assert_frozen(false);
 
         let dispute = Transaction::new_dispute(3);
-        execute_transaction(&dispute, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&dispute, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidAmount))
+        );
 
         client
             .assert_total(initial_amount())
             .assert_held(thousand())
             .assert_available(initial_amount().sub(thousand()))
-            .assert_frozen(false);
+            .assert_frozen(false)
+            .assert_stats(0, 1, 0);
+        assert_eq!(client.stats.disputed_volume, thousand());
     }
 
     #[test]
@@ -341,7 +583,10 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_dispute(4);
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&deposit, &mut client, &mut archived_transactions),
+            Err(Rejection::UnknownDeposit)
+        );
 
         client
             .assert_total(initial_amount())
@@ -355,7 +600,7 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_dispute(3);
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount())
@@ -364,7 +609,7 @@ mod tests {
             .assert_frozen(false);
 
         let resolve = Transaction::new_resolve(3);
-        execute_transaction(&resolve, &mut client, &mut archived_transactions);
+        execute_transaction(&resolve, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount())
@@ -378,7 +623,10 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let resolve = Transaction::new_resolve(5);
-        execute_transaction(&resolve, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&resolve, &mut client, &mut archived_transactions),
+            Err(Rejection::UnknownDeposit)
+        );
 
         client
             .assert_total(initial_amount())
@@ -392,7 +640,12 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let resolve = Transaction::new_resolve(3);
-        execute_transaction(&resolve, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&resolve, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidState(
+                DepositState::Settled
+            )))
+        );
 
         client
             .assert_total(initial_amount())
@@ -406,7 +659,7 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let deposit = Transaction::new_dispute(3);
-        execute_transaction(&deposit, &mut client, &mut archived_transactions);
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount())
@@ -415,15 +668,16 @@ mod tests {
             .assert_frozen(false);
 
         let resolve = Transaction::new_chargeback(3);
-        execute_transaction(&resolve, &mut client, &mut archived_transactions);
+        execute_transaction(&resolve, &mut client, &mut archived_transactions).unwrap();
 
         client
             .assert_total(initial_amount().sub(thousand()))
             .assert_held(Decimal::default())
             .assert_available(initial_amount().sub(thousand()))
-            .assert_frozen(true);
+            .assert_frozen(true)
+            .assert_stats(0, 1, 1);
 
-        archived_transactions.assert_removed(3);
+        archived_transactions.assert_state(3, DepositState::ChargedBack);
     }
 
     #[test]
@@ -431,7 +685,10 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let resolve = Transaction::new_chargeback(5);
-        execute_transaction(&resolve, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&resolve, &mut client, &mut archived_transactions),
+            Err(Rejection::UnknownDeposit)
+        );
 
         client
             .assert_total(initial_amount())
@@ -445,7 +702,12 @@ mod tests {
         let (mut client, mut archived_transactions) = initial_state();
 
         let resolve = Transaction::new_chargeback(3);
-        execute_transaction(&resolve, &mut client, &mut archived_transactions);
+        assert_eq!(
+            execute_transaction(&resolve, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidState(
+                DepositState::Settled
+            )))
+        );
 
         client
             .assert_total(initial_amount())
@@ -455,4 +717,422 @@ mod tests {
 
         archived_transactions.assert_disputed(3, false);
     }
+
+    /// 400.00
+    fn four_hundred() -> Decimal {
+        Decimal::new(400, 0)
+    }
+
+    #[test]
+    fn handle_partial_dispute() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3).with_amount(four_hundred());
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_total(initial_amount())
+            .assert_held(four_hundred())
+            .assert_available(initial_amount().sub(four_hundred()))
+            .assert_frozen(false);
+        assert_eq!(client.stats.disputed_volume, four_hundred());
+
+        archived_transactions
+            .assert_amount(3, thousand())
+            .assert_disputed_amount(3, four_hundred());
+    }
+
+    #[test]
+    fn handle_concurrent_partial_disputes() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3).with_amount(four_hundred());
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_held(four_hundred().add(four_hundred()))
+            .assert_stats(0, 2, 0);
+
+        // only 200 are left undisputed
+        assert_eq!(
+            execute_transaction(&dispute, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidAmount))
+        );
+
+        client
+            .assert_held(four_hundred().add(four_hundred()))
+            .assert_stats(0, 2, 0);
+
+        // a dispute without amount takes the remainder
+        let dispute = Transaction::new_dispute(3);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_total(initial_amount())
+            .assert_held(thousand())
+            .assert_stats(0, 3, 0);
+
+        archived_transactions.assert_disputed_amount(3, thousand());
+    }
+
+    #[test]
+    fn ignore_dispute_exceeding_deposit() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3).with_amount(thousand().add(four_hundred()));
+        assert_eq!(
+            execute_transaction(&dispute, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidAmount))
+        );
+
+        client
+            .assert_total(initial_amount())
+            .assert_held(Decimal::default())
+            .assert_stats(0, 0, 0);
+
+        archived_transactions.assert_disputed(3, false);
+    }
+
+    #[test]
+    fn handle_partial_resolve() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        let resolve = Transaction::new_resolve(3).with_amount(four_hundred());
+        execute_transaction(&resolve, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_total(initial_amount())
+            .assert_held(thousand().sub(four_hundred()))
+            .assert_frozen(false);
+
+        // resolving more than is disputed is ignored
+        let resolve = Transaction::new_resolve(3).with_amount(thousand());
+        assert_eq!(
+            execute_transaction(&resolve, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidAmount))
+        );
+
+        client.assert_held(thousand().sub(four_hundred()));
+
+        // a resolve without amount releases everything that is still disputed
+        let resolve = Transaction::new_resolve(3);
+        execute_transaction(&resolve, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_total(initial_amount())
+            .assert_held(Decimal::default());
+
+        archived_transactions.assert_disputed(3, false);
+    }
+
+    #[test]
+    fn handle_partial_chargeback() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3).with_amount(four_hundred());
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        let chargeback = Transaction::new_chargeback(3);
+        execute_transaction(&chargeback, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_total(initial_amount().sub(four_hundred()))
+            .assert_held(Decimal::default())
+            .assert_available(initial_amount().sub(four_hundred()))
+            .assert_frozen(true)
+            .assert_stats(0, 1, 1);
+
+        // the undisputed part of the deposit is kept
+        archived_transactions
+            .assert_amount(3, thousand())
+            .assert_disputed(3, false);
+    }
+
+    #[test]
+    fn handle_chargeback_of_part_of_dispute() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        let chargeback = Transaction::new_chargeback(3).with_amount(four_hundred());
+        execute_transaction(&chargeback, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_total(initial_amount().sub(four_hundred()))
+            .assert_held(thousand().sub(four_hundred()))
+            .assert_frozen(true);
+
+        let mut archived_transactions =
+            archived_transactions.assert_disputed_amount(3, thousand().sub(four_hundred()));
+
+        let chargeback = Transaction::new_chargeback(3);
+        execute_transaction(&chargeback, &mut client, &mut archived_transactions).unwrap();
+
+        client
+            .assert_total(initial_amount().sub(thousand()))
+            .assert_held(Decimal::default());
+
+        archived_transactions.assert_state(3, DepositState::ChargedBack);
+    }
+
+    #[test]
+    fn ignore_resolve_after_chargeback() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+        let chargeback = Transaction::new_chargeback(3);
+        execute_transaction(&chargeback, &mut client, &mut archived_transactions).unwrap();
+        let resolve = Transaction::new_resolve(3);
+        assert_eq!(
+            execute_transaction(&resolve, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidState(
+                DepositState::ChargedBack
+            )))
+        );
+        assert_eq!(
+            execute_transaction(&dispute, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidState(
+                DepositState::ChargedBack
+            )))
+        );
+
+        client
+            .assert_total(initial_amount().sub(thousand()))
+            .assert_held(Decimal::default())
+            .assert_stats(0, 1, 1);
+
+        archived_transactions.assert_state(3, DepositState::ChargedBack);
+    }
+
+    #[test]
+    fn handle_valid_reversal() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+        let chargeback = Transaction::new_chargeback(3);
+        execute_transaction(&chargeback, &mut client, &mut archived_transactions).unwrap();
+        let reversal = Transaction::new_reversal(3);
+        execute_transaction(&reversal, &mut client, &mut archived_transactions).unwrap();
+
+        // the client stays locked, unlocking is up to the caller
+        client
+            .assert_total(initial_amount())
+            .assert_held(Decimal::default())
+            .assert_available(initial_amount())
+            .assert_frozen(true);
+        assert_eq!(client.stats.reversed_volume, thousand());
+
+        let mut archived_transactions =
+            archived_transactions.assert_state(3, DepositState::Reversed);
+
+        // a reversed deposit can be disputed again
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        client.assert_held(thousand()).assert_stats(0, 2, 1);
+        archived_transactions.assert_state(3, DepositState::Disputed);
+    }
+
+    #[test]
+    fn ignore_reversal_without_chargeback() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let reversal = Transaction::new_reversal(3);
+        assert_eq!(
+            execute_transaction(&reversal, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidState(
+                DepositState::Settled
+            )))
+        );
+
+        let dispute = Transaction::new_dispute(3);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+        assert_eq!(
+            execute_transaction(&reversal, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidState(
+                DepositState::Disputed
+            )))
+        );
+
+        client
+            .assert_total(initial_amount())
+            .assert_held(thousand());
+
+        archived_transactions.assert_state(3, DepositState::Disputed);
+    }
+
+    #[test]
+    fn handle_partial_reversal() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3);
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+        let chargeback = Transaction::new_chargeback(3);
+        execute_transaction(&chargeback, &mut client, &mut archived_transactions).unwrap();
+        let reversal = Transaction::new_reversal(3).with_amount(four_hundred());
+        execute_transaction(&reversal, &mut client, &mut archived_transactions).unwrap();
+
+        client.assert_total(initial_amount().sub(thousand()).add(four_hundred()));
+        let mut archived_transactions =
+            archived_transactions.assert_state(3, DepositState::ChargedBack);
+
+        let reversal = Transaction::new_reversal(3);
+        execute_transaction(&reversal, &mut client, &mut archived_transactions).unwrap();
+
+        client.assert_total(initial_amount());
+        archived_transactions.assert_state(3, DepositState::Reversed);
+    }
+
+    #[test]
+    fn ignore_dispute_of_other_clients_deposit() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let mut dispute = Transaction::new_dispute(3);
+        dispute.client_id = 2;
+        assert_eq!(
+            execute_transaction(&dispute, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(
+                TransitionError::ClientMismatch
+            ))
+        );
+
+        client
+            .assert_total(initial_amount())
+            .assert_held(Decimal::default())
+            .assert_stats(0, 0, 0);
+
+        archived_transactions.assert_state(3, DepositState::Settled);
+    }
+
+    #[test]
+    fn record_deposit_history() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let mut deposit = Transaction::new_deposit(thousand());
+        deposit.row = 1;
+        let mut dispute = Transaction::new_dispute(4);
+        dispute.row = 2;
+        let mut resolve = Transaction::new_resolve(4);
+        resolve.row = 3;
+        let mut chargeback = Transaction::new_chargeback(4);
+        chargeback.row = 5;
+
+        execute_transaction(&deposit, &mut client, &mut archived_transactions).unwrap();
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+        execute_transaction(&resolve, &mut client, &mut archived_transactions).unwrap();
+        dispute.row = 4;
+        execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+        execute_transaction(&chargeback, &mut client, &mut archived_transactions).unwrap();
+
+        let history = archived_transactions.get(&4).unwrap().history();
+        assert_eq!(history.deposited_at, 1);
+        assert_eq!((history.disputes, history.last_disputed_at), (2, Some(4)));
+        assert_eq!((history.resolves, history.last_resolved_at), (1, Some(3)));
+        assert_eq!(
+            (history.chargebacks, history.last_charged_back_at),
+            (1, Some(5))
+        );
+        assert_eq!((history.reversals, history.last_reversed_at), (0, None));
+    }
+
+    #[test]
+    fn reject_invalid_transitions() {
+        let mut deposit = ArchivedDeposit::new(1, thousand(), 0);
+
+        assert_eq!(
+            deposit.resolve(1, None, 1),
+            Err(TransitionError::InvalidState(DepositState::Settled))
+        );
+        assert_eq!(
+            deposit.reverse(1, None, 1),
+            Err(TransitionError::InvalidState(DepositState::Settled))
+        );
+        assert_eq!(
+            deposit.dispute(2, None, 1),
+            Err(TransitionError::ClientMismatch)
+        );
+        assert_eq!(
+            deposit.dispute(1, Some(thousand().neg()), 1),
+            Err(TransitionError::InvalidAmount)
+        );
+        assert_eq!(deposit.dispute(1, None, 1), Ok(thousand()));
+        assert_eq!(deposit.charge_back(1, None, 2), Ok(thousand()));
+        assert_eq!(
+            deposit.dispute(1, None, 3),
+            Err(TransitionError::InvalidState(DepositState::ChargedBack))
+        );
+    }
+
+    #[test]
+    fn emit_ledger_entries() {
+        let (mut client, mut archived_transactions) = initial_state();
+
+        let dispute = Transaction::new_dispute(3);
+        let effects =
+            execute_transaction(&dispute, &mut client, &mut archived_transactions).unwrap();
+
+        assert_eq!(effects.len(), 1);
+        assert_eq!(effects[0].event, LedgerEvent::MoveToHeld(thousand()));
+        assert_eq!(effects[0].held, thousand());
+        assert_eq!(effects[0].available, initial_amount().sub(thousand()));
+
+        let chargeback = Transaction::new_chargeback(3);
+        let effects =
+            execute_transaction(&chargeback, &mut client, &mut archived_transactions).unwrap();
+
+        let events: Vec<LedgerEvent> = effects.iter().map(|entry| entry.event).collect();
+        assert_eq!(
+            events,
+            vec![LedgerEvent::DebitHeld(thousand()), LedgerEvent::Lock]
+        );
+        assert!(!effects[0].locked);
+        assert!(effects[1].locked);
+        assert_eq!(effects[1].total, initial_amount().sub(thousand()));
+
+        // rejected transactions have no effects
+        assert_eq!(
+            execute_transaction(&chargeback, &mut client, &mut archived_transactions),
+            Err(Rejection::InvalidTransition(TransitionError::InvalidState(
+                DepositState::ChargedBack
+            )))
+        );
+    }
+
+    #[test]
+    fn client_is_fold_over_events() {
+        let (mut client, mut archived_transactions) = initial_state();
+        let mut folded = Client {
+            total: initial_amount(),
+            ..Client::new()
+        };
+
+        let transactions = vec![
+            Transaction::new_withdrawal(four_hundred()),
+            Transaction::new_dispute(2),
+            Transaction::new_dispute(3),
+            Transaction::new_resolve(2),
+            Transaction::new_chargeback(3),
+            Transaction::new_reversal(3),
+        ];
+        for transaction in transactions {
+            // rejected transactions have no effects
+            for entry in execute_transaction(&transaction, &mut client, &mut archived_transactions)
+                .into_iter()
+                .flatten()
+            {
+                folded.apply(&entry.event);
+            }
+        }
+
+        folded
+            .assert_total(client.total)
+            .assert_held(client.held)
+            .assert_frozen(client.locked);
+    }
 }
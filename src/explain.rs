@@ -0,0 +1,74 @@
+use crate::rejection::Rejection;
+use crate::transaction::{Transaction, TransactionType, TxId};
+use crate::Engine;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+pub(crate) const STEP_HEADERS: &[&str] = &[
+    "row",
+    "type",
+    "tx",
+    "amount",
+    "outcome",
+    "reason",
+    "events",
+    "available",
+    "held",
+    "total",
+    "locked",
+];
+
+/// A transaction of the explained client, what it did and the balances of the client afterwards
+#[derive(Debug, Serialize)]
+pub(crate) struct Step {
+    row: u64,
+    r#type: TransactionType,
+    tx: TxId,
+    amount: Option<Decimal>,
+    outcome: &'static str,
+    /// why the transaction was rejected
+    reason: Option<String>,
+    /// names of all ledger events the transaction caused, separated by spaces
+    events: String,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+impl Step {
+    /// Processes the transaction, the engine has to record its ledger
+    pub(crate) fn process(engine: &mut Engine, transaction: Transaction) -> Step {
+        let (r#type, client_id, tx, amount) = (
+            transaction.r#type,
+            transaction.client_id,
+            transaction.tx_id,
+            transaction.amount,
+        );
+        let result = engine.process(transaction);
+        let events: Vec<&str> = engine
+            .drain_ledger()
+            .map(|entry| entry.event.name())
+            .collect();
+        // the client always exists after one of its transactions was processed
+        let client = engine.client_output(client_id).unwrap();
+
+        Step {
+            row: engine.rows(),
+            r#type,
+            tx,
+            amount,
+            outcome: if result.is_ok() {
+                "applied"
+            } else {
+                "rejected"
+            },
+            reason: result.err().as_ref().map(Rejection::to_string),
+            events: events.join(" "),
+            available: client.available,
+            held: client.held,
+            total: client.total,
+            locked: client.locked,
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::client::Client;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// Ratios above which a client is flagged or locked automatically.
+/// Ratios are taken relative to the number of accepted deposits of the client.
+#[derive(Debug, Default, Clone)]
+pub struct FraudThresholds {
+    /// ratios are only evaluated once a client has at least this many deposits
+    pub min_deposits: u32,
+    pub flag_dispute_ratio: Option<Decimal>,
+    pub lock_dispute_ratio: Option<Decimal>,
+    pub flag_chargeback_ratio: Option<Decimal>,
+    pub lock_chargeback_ratio: Option<Decimal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum FraudAction {
+    Flag,
+    Lock,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct FraudFlag {
+    pub action: FraudAction,
+    pub reason: String,
+}
+
+impl FraudThresholds {
+    pub fn is_enabled(&self) -> bool {
+        self.flag_dispute_ratio.is_some()
+            || self.lock_dispute_ratio.is_some()
+            || self.flag_chargeback_ratio.is_some()
+            || self.lock_chargeback_ratio.is_some()
+    }
+
+    /// Flags the client if one of its ratios exceeds a threshold, returns if the client has to be locked.
+    /// A flag is sticky, it is only replaced when a lock threshold is hit later on.
+    pub(crate) fn evaluate(&self, client: &mut Client) -> bool {
+        let stats = &client.stats;
+        if stats.deposits == 0 || stats.deposits < self.min_deposits {
+            return false;
+        }
+
+        let deposits = Decimal::from(stats.deposits);
+        let ratios = [
+            ("dispute", Decimal::from(stats.disputes) / deposits),
+            ("chargeback", Decimal::from(stats.chargebacks) / deposits),
+        ];
+        let thresholds = [
+            (self.lock_dispute_ratio, self.flag_dispute_ratio),
+            (self.lock_chargeback_ratio, self.flag_chargeback_ratio),
+        ];
+
+        for ((name, ratio), (lock, flag)) in ratios.iter().zip(thresholds.iter()) {
+            let reason = |threshold: Decimal| {
+                format!(
+                    "{} ratio {} exceeds {}",
+                    name,
+                    ratio.round_dp(4).normalize(),
+                    threshold
+                )
+            };
+
+            if let Some(threshold) = lock.filter(|threshold| ratio > threshold) {
+                client.flag = Some(FraudFlag {
+                    action: FraudAction::Lock,
+                    reason: reason(threshold),
+                });
+                return true;
+            }
+
+            if let Some(threshold) = flag.filter(|threshold| ratio > threshold) {
+                if client.flag.is_none() {
+                    client.flag = Some(FraudFlag {
+                        action: FraudAction::Flag,
+                        reason: reason(threshold),
+                    });
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::fraud::{FraudAction, FraudThresholds};
+    use rust_decimal::Decimal;
+
+    fn client_with(deposits: u32, disputes: u32, chargebacks: u32) -> Client {
+        let mut client = Client::new();
+        client.stats.deposits = deposits;
+        client.stats.disputes = disputes;
+        client.stats.chargebacks = chargebacks;
+        client
+    }
+
+    /// 0.5
+    fn half() -> Decimal {
+        Decimal::new(5, 1)
+    }
+
+    #[test]
+    fn disabled_thresholds_never_flag() {
+        let mut client = client_with(1, 1, 1);
+        let thresholds = FraudThresholds::default();
+
+        assert!(!thresholds.evaluate(&mut client));
+        assert!(!thresholds.is_enabled());
+        assert_eq!(client.flag, None);
+    }
+
+    #[test]
+    fn flag_on_dispute_ratio() {
+        let mut client = client_with(4, 3, 0);
+        let thresholds = FraudThresholds {
+            flag_dispute_ratio: Some(half()),
+            ..Default::default()
+        };
+
+        assert!(!thresholds.evaluate(&mut client));
+
+        let flag = client.flag.unwrap();
+        assert_eq!(flag.action, FraudAction::Flag);
+        assert_eq!(flag.reason, "dispute ratio 0.75 exceeds 0.5");
+    }
+
+    #[test]
+    fn ratio_equal_to_threshold_is_accepted() {
+        let mut client = client_with(4, 2, 0);
+        let thresholds = FraudThresholds {
+            flag_dispute_ratio: Some(half()),
+            ..Default::default()
+        };
+
+        assert!(!thresholds.evaluate(&mut client));
+        assert_eq!(client.flag, None);
+    }
+
+    #[test]
+    fn lock_on_chargeback_ratio_replaces_flag() {
+        let mut client = client_with(2, 2, 0);
+        let thresholds = FraudThresholds {
+            flag_dispute_ratio: Some(half()),
+            lock_chargeback_ratio: Some(half()),
+            ..Default::default()
+        };
+
+        assert!(!thresholds.evaluate(&mut client));
+        assert_eq!(client.flag.as_ref().unwrap().action, FraudAction::Flag);
+
+        client.stats.chargebacks = 2;
+        assert!(thresholds.evaluate(&mut client));
+
+        let flag = client.flag.unwrap();
+        assert_eq!(flag.action, FraudAction::Lock);
+        assert_eq!(flag.reason, "chargeback ratio 1 exceeds 0.5");
+    }
+
+    #[test]
+    fn wait_for_min_deposits() {
+        let mut client = client_with(1, 1, 0);
+        let thresholds = FraudThresholds {
+            min_deposits: 2,
+            lock_dispute_ratio: Some(half()),
+            ..Default::default()
+        };
+
+        assert!(!thresholds.evaluate(&mut client));
+        assert_eq!(client.flag, None);
+    }
+}
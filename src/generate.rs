@@ -0,0 +1,388 @@
+use crate::archived_deposit::DepositState;
+use crate::csv_writer_with_header;
+use crate::transaction::{ClientId, TransactionType, TxId};
+use rand::distributions::WeightedIndex;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use rust_decimal::Decimal;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Amounts are generated in ten-thousandths, the precision of the engine
+const AMOUNT_SCALE: u32 = 4;
+/// Largest deposit in ten-thousandths
+const MAX_DEPOSIT: i64 = 100_000_000;
+
+/// Options of the test data generator, the same options always generate the same transactions
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    pub seed: u64,
+    pub rows: u64,
+    /// clients get the ids 1 to `clients`
+    pub clients: ClientId,
+    /// relative frequency of every transaction type, types without weight are never generated
+    pub weights: Vec<(TransactionType, u32)>,
+    /// only generate transactions the engine applies: withdrawals within the available funds,
+    /// disputes, resolves, chargebacks and reversals of deposits in the right state and nothing for locked clients
+    /// but reversals. Without it references and amounts are random, so many transactions are rejected.
+    pub valid_only: bool,
+    /// write the final state of all clients to this csv file, requires `valid_only`
+    pub report: Option<PathBuf>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            seed: 0,
+            rows: 100_000,
+            clients: 10_000,
+            weights: vec![
+                (TransactionType::Deposit, 16),
+                (TransactionType::Withdrawal, 14),
+                (TransactionType::Dispute, 7),
+                (TransactionType::Resolve, 2),
+                (TransactionType::Chargeback, 1),
+                (TransactionType::Reversal, 0),
+            ],
+            valid_only: false,
+            report: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Row {
+    r#type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    amount: Option<Decimal>,
+}
+
+/// Balances of a client as the engine should compute them, in ten-thousandths
+#[derive(Default)]
+struct ModelClient {
+    available: i64,
+    held: i64,
+    locked: bool,
+    deposits: Vec<TxId>,
+}
+
+struct ModelDeposit {
+    client: ClientId,
+    amount: i64,
+    state: DepositState,
+}
+
+/// Generates transactions and, for valid transactions, tracks the balances they should result in
+pub(crate) struct Generator {
+    rng: ChaCha8Rng,
+    types: Vec<TransactionType>,
+    distribution: WeightedIndex<u32>,
+    valid_only: bool,
+    next_tx: TxId,
+    /// index is the client id - 1
+    clients: Vec<ModelClient>,
+    locked_clients: usize,
+    deposits: FxHashMap<TxId, ModelDeposit>,
+    /// deposits that were disputed or charged back at some point, their current state is checked when they are picked
+    disputed: Vec<TxId>,
+    charged_back: Vec<TxId>,
+}
+
+impl Generator {
+    pub(crate) fn new(config: &GeneratorConfig) -> Result<Self, Box<dyn Error>> {
+        if config.clients == 0 {
+            return Err("at least one client is required".into());
+        }
+        if config.report.is_some() && !config.valid_only {
+            return Err("the expected report requires valid transactions only".into());
+        }
+        let (types, weights): (Vec<TransactionType>, Vec<u32>) =
+            config.weights.iter().copied().unzip();
+        Ok(Generator {
+            rng: ChaCha8Rng::seed_from_u64(config.seed),
+            distribution: WeightedIndex::new(weights)?,
+            types,
+            valid_only: config.valid_only,
+            next_tx: 1,
+            clients: (0..config.clients)
+                .map(|_| ModelClient::default())
+                .collect(),
+            locked_clients: 0,
+            deposits: FxHashMap::default(),
+            disputed: Vec::new(),
+            charged_back: Vec::new(),
+        })
+    }
+
+    fn next(&mut self) -> Result<Row, Box<dyn Error>> {
+        let r#type = self.types[self.distribution.sample(&mut self.rng)];
+        let row = match r#type {
+            TransactionType::Deposit => None,
+            TransactionType::Withdrawal => self.withdrawal(),
+            TransactionType::Dispute => self.dispute(),
+            TransactionType::Resolve => self.settle_dispute(TransactionType::Resolve),
+            TransactionType::Chargeback => self.settle_dispute(TransactionType::Chargeback),
+            TransactionType::Reversal => self.reversal(),
+        };
+        // transactions that aren't possible in the current state are replaced by deposits
+        match row {
+            Some(row) => Ok(row),
+            None => self.deposit(),
+        }
+    }
+
+    fn deposit(&mut self) -> Result<Row, Box<dyn Error>> {
+        if self.valid_only && self.locked_clients == self.clients.len() {
+            return Err(
+                "all clients are locked, use more clients or a lower chargeback weight".into(),
+            );
+        }
+        let client = loop {
+            let client = self.random_client();
+            if !self.valid_only || !self.client(client).locked {
+                break client;
+            }
+        };
+        let amount = self.rng.gen_range(1..=MAX_DEPOSIT);
+        let tx = self.tx_id();
+        self.client(client).available += amount;
+        self.client(client).deposits.push(tx);
+        self.deposits.insert(
+            tx,
+            ModelDeposit {
+                client,
+                amount,
+                state: DepositState::Settled,
+            },
+        );
+        Ok(row(TransactionType::Deposit, client, tx, Some(amount)))
+    }
+
+    fn withdrawal(&mut self) -> Option<Row> {
+        let client = self.random_client();
+        let amount = if self.valid_only {
+            let (locked, available) = (self.client(client).locked, self.client(client).available);
+            if locked || available <= 0 {
+                return None;
+            }
+            let amount = self.rng.gen_range(1..=available);
+            self.client(client).available -= amount;
+            amount
+        } else {
+            self.rng.gen_range(1..=MAX_DEPOSIT)
+        };
+        let tx = self.tx_id();
+        Some(row(TransactionType::Withdrawal, client, tx, Some(amount)))
+    }
+
+    fn dispute(&mut self) -> Option<Row> {
+        let client = self.random_client();
+        if self.valid_only && self.client(client).locked {
+            return None;
+        }
+        let tx = self.random_deposit(client)?;
+        if self.valid_only {
+            let deposit = self.deposits.get_mut(&tx)?;
+            if !matches!(
+                deposit.state,
+                DepositState::Settled | DepositState::Reversed
+            ) {
+                return None;
+            }
+            deposit.state = DepositState::Disputed;
+            let amount = deposit.amount;
+            let state = self.client(client);
+            state.available -= amount;
+            state.held += amount;
+            self.disputed.push(tx);
+        }
+        Some(row(TransactionType::Dispute, client, tx, None))
+    }
+
+    /// resolves or charges back a disputed deposit
+    fn settle_dispute(&mut self, r#type: TransactionType) -> Option<Row> {
+        if !self.valid_only {
+            let client = self.random_client();
+            let tx = self.random_deposit(client)?;
+            return Some(row(r#type, client, tx, None));
+        }
+
+        let index = self.rng.gen_range(0..self.disputed.len().max(1));
+        let tx = *self.disputed.get(index)?;
+        let deposit = &self.deposits[&tx];
+        let (client, amount) = (deposit.client, deposit.amount);
+        if deposit.state != DepositState::Disputed || self.client(client).locked {
+            return None;
+        }
+        self.disputed.swap_remove(index);
+        let state = self.client(client);
+        state.held -= amount;
+        let deposit_state = if r#type == TransactionType::Resolve {
+            state.available += amount;
+            DepositState::Settled
+        } else {
+            state.locked = true;
+            self.locked_clients += 1;
+            self.charged_back.push(tx);
+            DepositState::ChargedBack
+        };
+        self.deposits.get_mut(&tx)?.state = deposit_state;
+        Some(row(r#type, client, tx, None))
+    }
+
+    /// reversals are the only transactions accepted for locked clients, the client stays locked
+    fn reversal(&mut self) -> Option<Row> {
+        if !self.valid_only {
+            let client = self.random_client();
+            let tx = self.random_deposit(client)?;
+            return Some(row(TransactionType::Reversal, client, tx, None));
+        }
+
+        let index = self.rng.gen_range(0..self.charged_back.len().max(1));
+        let tx = self.charged_back.get(index).copied()?;
+        self.charged_back.swap_remove(index);
+        let deposit = self.deposits.get_mut(&tx)?;
+        deposit.state = DepositState::Reversed;
+        let (client, amount) = (deposit.client, deposit.amount);
+        self.client(client).available += amount;
+        Some(row(TransactionType::Reversal, client, tx, None))
+    }
+
+    fn random_client(&mut self) -> ClientId {
+        self.rng.gen_range(1..=self.clients.len() as ClientId)
+    }
+
+    fn random_deposit(&mut self, client: ClientId) -> Option<TxId> {
+        self.clients[client as usize - 1]
+            .deposits
+            .choose(&mut self.rng)
+            .copied()
+    }
+
+    fn client(&mut self, client: ClientId) -> &mut ModelClient {
+        &mut self.clients[client as usize - 1]
+    }
+
+    fn tx_id(&mut self) -> TxId {
+        let tx = self.next_tx;
+        self.next_tx += 1;
+        tx
+    }
+
+    /// Writes the final state of every client that had a transaction, in the format of the engine output
+    pub(crate) fn write_report(&self, writer: impl Write) -> Result<(), Box<dyn Error>> {
+        let mut wtr = csv::Writer::from_writer(writer);
+        wtr.write_record(["client", "available", "held", "total", "locked"])?;
+        for (index, client) in self.clients.iter().enumerate() {
+            if client.deposits.is_empty() {
+                continue;
+            }
+            wtr.write_record([
+                (index + 1).to_string(),
+                to_decimal(client.available).to_string(),
+                to_decimal(client.held).to_string(),
+                to_decimal(client.available + client.held).to_string(),
+                client.locked.to_string(),
+            ])?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+fn row(r#type: TransactionType, client: ClientId, tx: TxId, amount: Option<i64>) -> Row {
+    Row {
+        r#type,
+        client,
+        tx,
+        amount: amount.map(to_decimal),
+    }
+}
+
+fn to_decimal(amount: i64) -> Decimal {
+    Decimal::new(amount, AMOUNT_SCALE).normalize()
+}
+
+/// Writes the given number of transactions as csv, always with a header so even no rows are a valid input
+pub(crate) fn write_transactions(
+    generator: &mut Generator,
+    rows: u64,
+    writer: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv_writer_with_header(writer, ["type", "client", "tx", "amount"])?;
+    for _ in 0..rows {
+        wtr.serialize(generator.next()?)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::generate::{write_transactions, Generator, GeneratorConfig};
+    use crate::TransactionType;
+
+    fn generate(config: &GeneratorConfig) -> String {
+        let mut output = Vec::new();
+        let mut generator = Generator::new(config).unwrap();
+        write_transactions(&mut generator, config.rows, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn same_seed_same_transactions() {
+        let config = GeneratorConfig {
+            rows: 1_000,
+            clients: 20,
+            ..Default::default()
+        };
+        assert_eq!(generate(&config), generate(&config));
+
+        let other_seed = GeneratorConfig {
+            seed: 1,
+            ..config.clone()
+        };
+        assert_ne!(generate(&config), generate(&other_seed));
+    }
+
+    #[test]
+    fn only_weighted_types() {
+        let config = GeneratorConfig {
+            rows: 100,
+            clients: 5,
+            weights: vec![
+                (TransactionType::Deposit, 1),
+                (TransactionType::Withdrawal, 1),
+            ],
+            ..Default::default()
+        };
+        let output = generate(&config);
+        assert!(output.starts_with("type,client,tx,amount\n"));
+        assert!(output
+            .lines()
+            .skip(1)
+            .all(|line| line.starts_with("deposit,") || line.starts_with("withdrawal,")));
+    }
+
+    #[test]
+    fn header_without_rows() {
+        let config = GeneratorConfig {
+            rows: 0,
+            ..Default::default()
+        };
+        assert_eq!(generate(&config), "type,client,tx,amount\n");
+    }
+
+    #[test]
+    fn report_requires_valid_only() {
+        let config = GeneratorConfig {
+            report: Some("report.csv".into()),
+            ..Default::default()
+        };
+        assert!(Generator::new(&config).is_err());
+    }
+}
@@ -0,0 +1,379 @@
+#[cfg(feature = "parquet")]
+use crate::columnar::ParquetTransactions;
+use crate::dialect::{CsvDialect, RecordNormalizer};
+use crate::transaction::Transaction;
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use csv_async::{AsyncDeserializer, ByteRecord};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader, Lines};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const PARQUET_MAGIC: &[u8] = b"PAR1";
+
+pub(crate) type InputStream = Box<dyn AsyncRead + Unpin + Send>;
+
+/// How transactions of several input files are combined into one stream
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum InputOrder {
+    /// one file after the other, in the given order
+    #[default]
+    Sequential,
+    /// merged by the timestamp column, every file has to be ordered by timestamp itself
+    Timestamp,
+    /// merged by transaction id, every file has to be ordered by transaction id itself
+    TxId,
+}
+
+/// Format of the input files
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum InputFormat {
+    /// by the extension of the file, `.csv` or `.jsonl`/`.ndjson` before a compression extension or `.parquet`,
+    /// otherwise by the magic bytes of parquet or json lines if the first character is a `{`
+    #[default]
+    Auto,
+    Csv,
+    /// one json object per line
+    JsonLines,
+    /// requires the `parquet` feature, parquet files are compressed internally and can't be compressed as a whole
+    Parquet,
+}
+
+impl InputFormat {
+    fn from_extension(path: &Path) -> Self {
+        let mut path = path.to_path_buf();
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "gz" || extension == "zst")
+        {
+            path.set_extension("");
+        }
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => InputFormat::Csv,
+            Some("jsonl") | Some("ndjson") => InputFormat::JsonLines,
+            Some("parquet") => InputFormat::Parquet,
+            _ => InputFormat::Auto,
+        }
+    }
+}
+
+/// Opens a file for reading, gzip and zstd compressed files are detected by their magic bytes
+/// and decompressed while they are read
+async fn open_decompressed(path: &Path) -> Result<InputStream, Box<dyn Error>> {
+    let mut file = BufReader::new(tokio::fs::File::open(path).await?);
+    // the buffer holds at least the first few bytes unless the file is shorter than that
+    let start = file.fill_buf().await?;
+    if start.starts_with(GZIP_MAGIC) {
+        let mut decoder = GzipDecoder::new(file);
+        // files concatenated with `cat a.gz b.gz` consist of several members
+        decoder.multiple_members(true);
+        Ok(Box::new(decoder))
+    } else if start.starts_with(ZSTD_MAGIC) {
+        let mut decoder = ZstdDecoder::new(file);
+        decoder.multiple_members(true);
+        Ok(Box::new(decoder))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Reads transactions one by one from a csv or json lines file
+pub(crate) enum TransactionReader {
+    Csv {
+        rdr: AsyncDeserializer<BufReader<InputStream>>,
+        headers: ByteRecord,
+        raw_record: ByteRecord,
+        /// rewrites records of dialects that differ from the default
+        normalizer: Option<RecordNormalizer>,
+    },
+    JsonLines {
+        lines: Lines<BufReader<InputStream>>,
+        /// line number of the last line read, counted from 1
+        line: u64,
+    },
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetTransactions),
+}
+
+impl TransactionReader {
+    pub(crate) async fn open(
+        input_file_path: &Path,
+        format: InputFormat,
+        dialect: &CsvDialect,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut file = BufReader::new(open_decompressed(input_file_path).await?);
+        let format = match format {
+            InputFormat::Auto => match InputFormat::from_extension(input_file_path) {
+                InputFormat::Auto => {
+                    let start = file.fill_buf().await?;
+                    if start.starts_with(PARQUET_MAGIC) {
+                        InputFormat::Parquet
+                    } else {
+                        match start.iter().find(|byte| !byte.is_ascii_whitespace()) {
+                            Some(b'{') => InputFormat::JsonLines,
+                            _ => InputFormat::Csv,
+                        }
+                    }
+                }
+                format => format,
+            },
+            format => format,
+        };
+        tracing::debug!(path = %input_file_path.display(), ?format, "opened input");
+
+        if format == InputFormat::Parquet {
+            // the footer of parquet files has to be read first, so they are read directly instead of as a stream
+            #[cfg(feature = "parquet")]
+            return Ok(TransactionReader::Parquet(ParquetTransactions::open(
+                input_file_path,
+            )?));
+            #[cfg(not(feature = "parquet"))]
+            return Err("parquet inputs require the parquet feature".into());
+        }
+
+        if format == InputFormat::JsonLines {
+            return Ok(TransactionReader::JsonLines {
+                lines: file.lines(),
+                line: 0,
+            });
+        }
+
+        Self::csv(file, dialect).await
+    }
+
+    /// Reads csv from a stream that is already decompressed
+    pub(crate) async fn csv(
+        stream: BufReader<InputStream>,
+        dialect: &CsvDialect,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut rdr = dialect.reader_builder().create_deserializer(stream);
+        let headers = if dialect.has_headers {
+            dialect.map_headers(rdr.byte_headers().await?)
+        } else {
+            dialect.map_headers(&ByteRecord::new())
+        };
+        let normalizer = dialect.normalizer(&headers);
+
+        Ok(TransactionReader::Csv {
+            rdr,
+            headers,
+            raw_record: ByteRecord::new(),
+            normalizer,
+        })
+    }
+
+    pub(crate) async fn next(&mut self) -> Result<Option<Transaction>, Box<dyn Error>> {
+        match self {
+            TransactionReader::Csv {
+                rdr,
+                headers,
+                raw_record,
+                normalizer,
+            } => {
+                if rdr.read_byte_record(raw_record).await? {
+                    let record = match normalizer {
+                        Some(normalizer) => normalizer.normalize(raw_record),
+                        None => raw_record,
+                    };
+                    Ok(Some(record.deserialize(Some(headers))?))
+                } else {
+                    Ok(None)
+                }
+            }
+            TransactionReader::JsonLines { lines, line } => {
+                match next_json_line(lines, line).await? {
+                    Some(text) => serde_json::from_str(&text)
+                        .map(Some)
+                        .map_err(|err| format!("line {}: {}", line, err).into()),
+                    None => Ok(None),
+                }
+            }
+            #[cfg(feature = "parquet")]
+            TransactionReader::Parquet(transactions) => transactions.next(),
+        }
+    }
+
+    /// Like `next`, but rows that can't be parsed are passed to `skipped` with their line and skipped.
+    /// Errors without a line or that don't advance the reader, e.g. an unreadable file, are returned.
+    pub(crate) async fn next_skipping_invalid(
+        &mut self,
+        mut skipped: impl FnMut(u64, Box<dyn Error>),
+    ) -> Result<Option<Transaction>, Box<dyn Error>> {
+        let mut last_error_line = None;
+        loop {
+            match self.next().await {
+                Err(err) => match self.line() {
+                    Some(line) if last_error_line != Some(line) => {
+                        last_error_line = Some(line);
+                        skipped(line, err);
+                    }
+                    _ => return Err(err),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// Line of the file the last transaction or error was read from, counted from 1.
+    /// None for parquet files, which have no lines.
+    pub(crate) fn line(&self) -> Option<u64> {
+        match self {
+            TransactionReader::Csv { raw_record, .. } => {
+                raw_record.position().map(|position| position.line())
+            }
+            TransactionReader::JsonLines { line, .. } => Some(*line),
+            #[cfg(feature = "parquet")]
+            TransactionReader::Parquet(_) => None,
+        }
+    }
+
+    /// Skips rows without deserializing them
+    pub(crate) async fn skip(&mut self, rows: u64) -> Result<(), Box<dyn Error>> {
+        for _ in 0..rows {
+            let more = match self {
+                TransactionReader::Csv {
+                    rdr, raw_record, ..
+                } => rdr.read_byte_record(raw_record).await?,
+                TransactionReader::JsonLines { lines, line } => {
+                    next_json_line(lines, line).await?.is_some()
+                }
+                #[cfg(feature = "parquet")]
+                TransactionReader::Parquet(transactions) => transactions.next()?.is_some(),
+            };
+            if !more {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Next non blank line, blank lines are skipped like empty rows of csv files
+async fn next_json_line(
+    lines: &mut Lines<BufReader<InputStream>>,
+    line: &mut u64,
+) -> Result<Option<String>, Box<dyn Error>> {
+    while let Some(text) = lines.next_line().await? {
+        *line += 1;
+        if !text.trim().is_empty() {
+            return Ok(Some(text));
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves the input arguments to files: directories are replaced by the files they contain,
+/// arguments containing `*`, `?` or `[` are expanded as glob patterns. Both are sorted by name.
+pub(crate) fn expand_inputs(inputs: &[&str]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut entries = Vec::new();
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?.path();
+                if entry.is_file() {
+                    entries.push(entry);
+                }
+            }
+            entries.sort();
+            files.extend(entries);
+        } else if input.contains(&['*', '?', '['][..]) {
+            let mut matches = glob::glob(input)?.collect::<Result<Vec<_>, _>>()?;
+            if matches.is_empty() {
+                return Err(format!("no input files match {}", input).into());
+            }
+            matches.sort();
+            files.extend(matches);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Combines the transactions of several files into a single stream, every transaction comes with the index of its file
+pub(crate) struct MergedInput {
+    readers: Vec<TransactionReader>,
+    order: InputOrder,
+    /// next transaction of every reader for merged orders, None once a reader is exhausted
+    heads: Vec<Option<Transaction>>,
+    current: usize,
+}
+
+impl MergedInput {
+    pub(crate) async fn open(
+        files: &[PathBuf],
+        order: InputOrder,
+        format: InputFormat,
+        dialect: &CsvDialect,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut readers = Vec::with_capacity(files.len());
+        for file in files {
+            readers.push(TransactionReader::open(file, format, dialect).await?);
+        }
+
+        let mut heads = Vec::new();
+        if order != InputOrder::Sequential {
+            for reader in &mut readers {
+                heads.push(reader.next().await?);
+            }
+        }
+
+        Ok(MergedInput {
+            readers,
+            order,
+            heads,
+            current: 0,
+        })
+    }
+
+    pub(crate) async fn next(&mut self) -> Result<Option<(usize, Transaction)>, Box<dyn Error>> {
+        if self.order == InputOrder::Sequential {
+            while self.current < self.readers.len() {
+                if let Some(transaction) = self.readers[self.current].next().await? {
+                    return Ok(Some((self.current, transaction)));
+                }
+                self.current += 1;
+            }
+            return Ok(None);
+        }
+
+        // few files are merged at a time, so a linear search for the smallest head is fine.
+        // On equal keys the earlier file comes first.
+        let mut next: Option<(usize, u64)> = None;
+        for (index, head) in self.heads.iter().enumerate() {
+            if let Some(transaction) = head {
+                let key = self.key(transaction)?;
+                if next.is_none_or(|(_, smallest)| key < smallest) {
+                    next = Some((index, key));
+                }
+            }
+        }
+
+        match next {
+            Some((index, _)) => {
+                let replacement = self.readers[index].next().await?;
+                let transaction = std::mem::replace(&mut self.heads[index], replacement);
+                Ok(transaction.map(|transaction| (index, transaction)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // transaction ids already are u64 with the wide-ids feature
+    #[allow(clippy::useless_conversion)]
+    fn key(&self, transaction: &Transaction) -> Result<u64, Box<dyn Error>> {
+        match self.order {
+            InputOrder::Timestamp => transaction.timestamp.ok_or_else(|| {
+                format!(
+                    "transaction {} has no timestamp, merging by timestamp requires a timestamp column",
+                    transaction.tx_id
+                )
+                .into()
+            }),
+            _ => Ok(u64::from(transaction.tx_id)),
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use crate::client::Client;
+use crate::ledger::{LedgerEntry, LedgerEvent};
+use crate::transaction::{ClientId, TransactionType, TxId};
+use crate::ClientsDataStructure;
+use rust_decimal::Decimal;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Accounts of the double-entry journal. Client accounts are owed to the clients, so they normally have
+/// credit balances, house cash normally has a debit balance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Account {
+    HouseCash,
+    /// funds taken from clients by chargebacks and not reversed yet
+    ChargebackLoss,
+    ClientAvailable(ClientId),
+    ClientHeld(ClientId),
+}
+
+impl Account {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Account::HouseCash => "house_cash",
+            Account::ChargebackLoss => "chargeback_loss",
+            Account::ClientAvailable(_) => "client_available",
+            Account::ClientHeld(_) => "client_held",
+        }
+    }
+
+    pub fn client(&self) -> Option<ClientId> {
+        match *self {
+            Account::ClientAvailable(client) | Account::ClientHeld(client) => Some(client),
+            Account::HouseCash | Account::ChargebackLoss => None,
+        }
+    }
+}
+
+/// Moves an amount from the credited to the debited account. Every entry has exactly one debit and one
+/// credit of the same amount, so the debits and credits of every transaction are equal by construction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub row: u64,
+    pub tx_id: TxId,
+    pub debit: Account,
+    pub credit: Account,
+    pub amount: Decimal,
+}
+
+impl JournalEntry {
+    /// The entry of a ledger event, locks and unlocks don't move any funds
+    pub(crate) fn from_ledger(r#type: TransactionType, entry: &LedgerEntry) -> Option<Self> {
+        let client = entry.client_id;
+        let (debit, credit, amount) = match entry.event {
+            LedgerEvent::CreditAvailable(amount) if r#type == TransactionType::Reversal => (
+                Account::ChargebackLoss,
+                Account::ClientAvailable(client),
+                amount,
+            ),
+            LedgerEvent::CreditAvailable(amount) => {
+                (Account::HouseCash, Account::ClientAvailable(client), amount)
+            }
+            LedgerEvent::DebitAvailable(amount) => {
+                (Account::ClientAvailable(client), Account::HouseCash, amount)
+            }
+            LedgerEvent::MoveToHeld(amount) => (
+                Account::ClientAvailable(client),
+                Account::ClientHeld(client),
+                amount,
+            ),
+            LedgerEvent::ReleaseHeld(amount) => (
+                Account::ClientHeld(client),
+                Account::ClientAvailable(client),
+                amount,
+            ),
+            LedgerEvent::DebitHeld(amount) => {
+                (Account::ClientHeld(client), Account::ChargebackLoss, amount)
+            }
+            LedgerEvent::Lock | LedgerEvent::Unlock => return None,
+        };
+        Some(JournalEntry {
+            row: entry.row,
+            tx_id: entry.tx_id,
+            debit,
+            credit,
+            amount,
+        })
+    }
+}
+
+/// Debits and credits of an account
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Balance {
+    debit: Decimal,
+    credit: Decimal,
+}
+
+/// Balances of all accounts, posted from the ledger events of every applied transaction
+#[derive(Debug, Default)]
+pub(crate) struct Journal {
+    balances: FxHashMap<Account, Balance>,
+    /// entries that were not yet taken by `drain`, only recorded when they are exported
+    entries: Vec<JournalEntry>,
+    record_entries: bool,
+}
+
+impl Journal {
+    pub(crate) fn new(record_entries: bool) -> Self {
+        Journal {
+            record_entries,
+            ..Journal::default()
+        }
+    }
+
+    pub(crate) fn post(&mut self, r#type: TransactionType, entries: &[LedgerEntry]) {
+        for entry in entries {
+            if let Some(entry) = JournalEntry::from_ledger(r#type, entry) {
+                self.balances.entry(entry.debit).or_default().debit += entry.amount;
+                self.balances.entry(entry.credit).or_default().credit += entry.amount;
+                if self.record_entries {
+                    self.entries.push(entry);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = JournalEntry> + '_ {
+        self.entries.drain(..)
+    }
+
+    /// debits minus credits
+    fn balance(&self, account: Account) -> Decimal {
+        self.balances
+            .get(&account)
+            .map(|balance| balance.debit - balance.credit)
+            .unwrap_or_default()
+    }
+
+    /// credits minus debits, the balance of client accounts
+    fn credit_balance(&self, account: Account) -> Decimal {
+        self.balances
+            .get(&account)
+            .map(|balance| balance.credit - balance.debit)
+            .unwrap_or_default()
+    }
+
+    /// Descriptions of every violated invariant: debits equal credits, the client accounts match the balances of
+    /// the clients and the sum of all client totals equals the net cash in, deposits minus withdrawals minus
+    /// chargebacks that weren't reversed
+    pub(crate) fn check(&self, clients: &ClientsDataStructure) -> Vec<String> {
+        let mut violations = Vec::new();
+        let (debits, credits) = self.balances.values().fold(
+            (Decimal::ZERO, Decimal::ZERO),
+            |(debits, credits), balance| (debits + balance.debit, credits + balance.credit),
+        );
+        if debits != credits {
+            violations.push(format!(
+                "debits of {} don't equal credits of {}",
+                debits, credits
+            ));
+        }
+
+        let mut sorted: Vec<(&ClientId, &Client)> = clients.iter().collect();
+        sorted.sort_unstable_by_key(|(id, _)| **id);
+        let mut client_totals = Decimal::ZERO;
+        for (id, client) in sorted {
+            client_totals += client.total;
+            let accounts = [
+                (Account::ClientAvailable(*id), client.available()),
+                (Account::ClientHeld(*id), client.held),
+            ];
+            for (account, expected) in accounts {
+                let journal = self.credit_balance(account);
+                if journal != expected {
+                    violations.push(format!(
+                        "client {} {}: journal has {}, client has {}",
+                        id,
+                        account.name(),
+                        journal,
+                        expected
+                    ));
+                }
+            }
+        }
+        for account in self.balances.keys() {
+            match account.client() {
+                Some(client) if !clients.contains_key(&client) => violations.push(format!(
+                    "client {} {}: journal has {}, but the client doesn't exist",
+                    client,
+                    account.name(),
+                    self.credit_balance(*account)
+                )),
+                _ => {}
+            }
+        }
+
+        let net_cash_in = self.balance(Account::HouseCash) + self.balance(Account::ChargebackLoss);
+        if client_totals != net_cash_in {
+            violations.push(format!(
+                "client totals of {} don't equal the net cash in of {}",
+                client_totals, net_cash_in
+            ));
+        }
+        violations
+    }
+
+    /// Writes the balance of every account on its debit or credit side and the totals of both sides as csv
+    pub(crate) fn write_trial_balance(&self, writer: impl Write) -> Result<(), Box<dyn Error>> {
+        let accounts: BTreeMap<Account, Balance> = self
+            .balances
+            .iter()
+            .map(|(account, balance)| (*account, *balance))
+            .collect();
+        let mut wtr = csv::Writer::from_writer(writer);
+        let mut totals = Balance::default();
+        for (account, balance) in accounts {
+            let net = balance.debit - balance.credit;
+            let (debit, credit) = if net.is_sign_negative() {
+                (Decimal::ZERO, -net)
+            } else {
+                (net, Decimal::ZERO)
+            };
+            totals.debit += debit;
+            totals.credit += credit;
+            wtr.serialize(TrialBalanceRecord {
+                account: account.name(),
+                client: account.client(),
+                debit,
+                credit,
+            })?;
+        }
+        wtr.serialize(TrialBalanceRecord {
+            account: "total",
+            client: None,
+            debit: totals.debit,
+            credit: totals.credit,
+        })?;
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct TrialBalanceRecord {
+    account: &'static str,
+    client: Option<ClientId>,
+    debit: Decimal,
+    credit: Decimal,
+}
+
+/// Flat representation of one side of an entry, every entry is exported as a debit and a credit line
+#[derive(Serialize)]
+struct JournalRecord {
+    row: u64,
+    tx: TxId,
+    account: &'static str,
+    client: Option<ClientId>,
+    debit: Option<Decimal>,
+    credit: Option<Decimal>,
+}
+
+/// Streams journal entries to a csv file
+pub(crate) struct JournalWriter {
+    wtr: csv::Writer<File>,
+}
+
+impl JournalWriter {
+    pub(crate) fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(JournalWriter {
+            wtr: csv::Writer::from_path(path)?,
+        })
+    }
+
+    pub(crate) fn write(&mut self, entry: &JournalEntry) -> Result<(), Box<dyn Error>> {
+        self.wtr.serialize(JournalRecord {
+            row: entry.row,
+            tx: entry.tx_id,
+            account: entry.debit.name(),
+            client: entry.debit.client(),
+            debit: Some(entry.amount),
+            credit: None,
+        })?;
+        self.wtr.serialize(JournalRecord {
+            row: entry.row,
+            tx: entry.tx_id,
+            account: entry.credit.name(),
+            client: entry.credit.client(),
+            debit: None,
+            credit: Some(entry.amount),
+        })?;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<(), Box<dyn Error>> {
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::journal::{Account, Journal};
+    use crate::ledger::LedgerEvent;
+    use crate::{ClientsDataStructure, Config, Engine, Transaction, TransactionType};
+    use rust_decimal::Decimal;
+
+    fn transaction(
+        r#type: TransactionType,
+        client: u16,
+        tx: u32,
+        amount: Option<i64>,
+    ) -> Transaction {
+        Transaction::new(
+            r#type,
+            client as _,
+            tx as _,
+            amount.map(|amount| Decimal::new(amount, 0)),
+        )
+    }
+
+    #[test]
+    fn every_transaction_type_balances() {
+        let mut engine = Engine::new(Config::default()).with_journal();
+        let transactions = [
+            transaction(TransactionType::Deposit, 1, 1, Some(10)),
+            transaction(TransactionType::Withdrawal, 1, 2, Some(3)),
+            transaction(TransactionType::Dispute, 1, 1, Some(4)),
+            transaction(TransactionType::Resolve, 1, 1, Some(1)),
+            transaction(TransactionType::Chargeback, 1, 1, None),
+            transaction(TransactionType::Reversal, 1, 1, Some(2)),
+        ];
+        for transaction in transactions {
+            engine.process(transaction).unwrap();
+        }
+
+        let entries: Vec<(Account, Account, Decimal)> = engine
+            .drain_journal()
+            .map(|entry| (entry.debit, entry.credit, entry.amount))
+            .collect();
+        let (available, held) = (Account::ClientAvailable(1), Account::ClientHeld(1));
+        assert_eq!(
+            entries,
+            [
+                (Account::HouseCash, available, Decimal::new(10, 0)),
+                (available, Account::HouseCash, Decimal::new(3, 0)),
+                (available, held, Decimal::new(4, 0)),
+                (held, available, Decimal::new(1, 0)),
+                (held, Account::ChargebackLoss, Decimal::new(3, 0)),
+                (Account::ChargebackLoss, available, Decimal::new(2, 0)),
+            ]
+        );
+        assert!(engine.journal_violations().is_empty());
+
+        let mut trial_balance = Vec::new();
+        engine
+            .journal()
+            .unwrap()
+            .write_trial_balance(&mut trial_balance)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(trial_balance).unwrap(),
+            "account,client,debit,credit\n\
+             house_cash,,7,0\n\
+             chargeback_loss,,0,1\n\
+             client_available,1,0,6\n\
+             client_held,1,0,0\n\
+             total,,7,7\n"
+        );
+    }
+
+    #[test]
+    fn check_reports_mismatching_clients() {
+        let mut engine = Engine::new(Config::default()).with_journal();
+        engine
+            .process(transaction(TransactionType::Deposit, 1, 1, Some(5)))
+            .unwrap();
+        let journal: &Journal = engine.journal().unwrap();
+
+        let mut clients = ClientsDataStructure::default();
+        let mut client = Client::new();
+        client.apply(&LedgerEvent::CreditAvailable(Decimal::new(4, 0)));
+        clients.insert(2, client);
+        assert_eq!(
+            journal.check(&clients),
+            [
+                "client 2 client_available: journal has 0, client has 4",
+                "client 1 client_available: journal has 5, but the client doesn't exist",
+                "client totals of 4 don't equal the net cash in of 5",
+            ]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::transaction::{ClientId, TxId};
+use arrayvec::ArrayVec;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// A single change to the balances or the lock of a client. Client states are a fold over these events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedgerEvent {
+    /// deposit or reversal
+    CreditAvailable(Decimal),
+    /// withdrawal
+    DebitAvailable(Decimal),
+    /// dispute
+    MoveToHeld(Decimal),
+    /// resolve
+    ReleaseHeld(Decimal),
+    /// chargeback
+    DebitHeld(Decimal),
+    Lock,
+    Unlock,
+}
+
+/// An event together with the transaction that caused it and the balances of the client after it was applied
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedgerEntry {
+    pub row: u64,
+    pub client_id: ClientId,
+    pub tx_id: TxId,
+    pub event: LedgerEvent,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+/// All events a single transaction can cause, at most a balance change, an unlock and a lock
+pub(crate) type Effects = ArrayVec<LedgerEntry, 4>;
+
+impl LedgerEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LedgerEvent::CreditAvailable(_) => "credit_available",
+            LedgerEvent::DebitAvailable(_) => "debit_available",
+            LedgerEvent::MoveToHeld(_) => "move_to_held",
+            LedgerEvent::ReleaseHeld(_) => "release_held",
+            LedgerEvent::DebitHeld(_) => "debit_held",
+            LedgerEvent::Lock => "lock",
+            LedgerEvent::Unlock => "unlock",
+        }
+    }
+
+    pub fn amount(&self) -> Option<Decimal> {
+        match *self {
+            LedgerEvent::CreditAvailable(amount)
+            | LedgerEvent::DebitAvailable(amount)
+            | LedgerEvent::MoveToHeld(amount)
+            | LedgerEvent::ReleaseHeld(amount)
+            | LedgerEvent::DebitHeld(amount) => Some(amount),
+            LedgerEvent::Lock | LedgerEvent::Unlock => None,
+        }
+    }
+}
+
+/// flat representation of an entry, so csv and json exports have the same columns
+#[derive(Debug, Serialize)]
+struct LedgerRecord {
+    row: u64,
+    client: ClientId,
+    tx: TxId,
+    event: &'static str,
+    amount: Option<Decimal>,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+impl From<&LedgerEntry> for LedgerRecord {
+    fn from(entry: &LedgerEntry) -> Self {
+        LedgerRecord {
+            row: entry.row,
+            client: entry.client_id,
+            tx: entry.tx_id,
+            event: entry.event.name(),
+            amount: entry.event.amount(),
+            available: entry.available,
+            held: entry.held,
+            total: entry.total,
+            locked: entry.locked,
+        }
+    }
+}
+
+/// Streams ledger entries to a file, as a json array for `.json` files and as csv otherwise
+pub(crate) enum LedgerWriter {
+    Csv(Box<csv::Writer<File>>),
+    Json {
+        writer: BufWriter<File>,
+        first: bool,
+    },
+}
+
+impl LedgerWriter {
+    pub(crate) fn create(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::create(path)?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            let mut writer = BufWriter::new(file);
+            writer.write_all(b"[")?;
+            Ok(LedgerWriter::Json {
+                writer,
+                first: true,
+            })
+        } else {
+            Ok(LedgerWriter::Csv(Box::new(csv::Writer::from_writer(file))))
+        }
+    }
+
+    pub(crate) fn write(&mut self, entry: &LedgerEntry) -> Result<(), Box<dyn Error>> {
+        let record = LedgerRecord::from(entry);
+        match self {
+            LedgerWriter::Csv(wtr) => wtr.serialize(record)?,
+            LedgerWriter::Json { writer, first } => {
+                if !*first {
+                    writer.write_all(b",")?;
+                }
+                *first = false;
+                writer.write_all(b"\n")?;
+                serde_json::to_writer(&mut *writer, &record)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> Result<(), Box<dyn Error>> {
+        match self {
+            LedgerWriter::Csv(mut wtr) => wtr.flush()?,
+            LedgerWriter::Json { mut writer, .. } => {
+                writer.write_all(b"\n]\n")?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
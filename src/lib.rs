@@ -1,56 +1,432 @@
 mod archived_deposit;
+mod audit;
+mod checkpoint;
 mod client;
+#[cfg(feature = "parquet")]
+mod columnar;
+mod config;
+mod dialect;
+mod diff;
+mod engine;
 mod execute_transaction;
+mod explain;
+mod fraud;
+mod generate;
+mod input;
+mod journal;
+mod ledger;
+mod rejection;
+mod serve;
+mod snapshot;
+mod summary;
 mod transaction;
-use crate::archived_deposit::ArchivedDeposit;
+mod validate;
+pub use crate::archived_deposit::{ArchivedDeposit, DepositHistory, DepositState, TransitionError};
+use crate::checkpoint::CheckpointWriter;
+pub use crate::checkpoint::{CheckpointConfig, CheckpointInterval, QueryTarget};
 use crate::client::Client;
-use crate::client::ClientOutput;
-use crate::execute_transaction::execute_transaction;
-use crate::transaction::Transaction;
-use csv_async::AsyncReaderBuilder;
+pub use crate::client::ClientOutput;
+pub use crate::config::Config;
+pub use crate::dialect::CsvDialect;
+use crate::diff::Report;
+pub use crate::engine::Engine;
+use crate::explain::{Step, STEP_HEADERS};
+pub use crate::fraud::FraudThresholds;
+use crate::generate::Generator;
+pub use crate::generate::GeneratorConfig;
+pub use crate::input::{InputFormat, InputOrder};
+use crate::input::{InputStream, MergedInput, TransactionReader};
+use crate::journal::JournalWriter;
+pub use crate::journal::{Account, JournalEntry};
+use crate::ledger::LedgerWriter;
+pub use crate::ledger::{LedgerEntry, LedgerEvent};
+pub use crate::rejection::Rejection;
+use crate::serve::ServerMetrics;
+use crate::snapshot::SnapshotWriter;
+pub use crate::snapshot::{SnapshotConfig, SnapshotInterval};
+pub use crate::summary::MetricsOutput;
+use crate::summary::{FileStatistics, Metrics};
+pub use crate::transaction::{ClientId, Transaction, TransactionType, TxId};
+use crate::validate::Validator;
 use rustc_hash::FxHashMap;
 use std::error::Error;
+use std::fs::File;
 use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::net::TcpListener;
+use tracing::Instrument;
 
 // FxHashmap since we won't have any key collisions and want faster lookup on int keys
 // we don't need client ordering, otherwise a BTreeMap would give ordering
-type ClientsDataStructure = FxHashMap<u16, Client>;
-type TransactionsDataStructure = FxHashMap<u32, ArchivedDeposit>;
+type ClientsDataStructure = FxHashMap<ClientId, Client>;
+type TransactionsDataStructure = FxHashMap<TxId, ArchivedDeposit>;
 
-pub async fn core_logic(input_file_path: &str) -> Result<(), Box<dyn Error>> {
-    let mut clients = ClientsDataStructure::default();
-    let mut transactions = TransactionsDataStructure::default();
+/// Processes all inputs into one shared state. Inputs can be files, directories or glob patterns.
+pub async fn core_logic(input_file_paths: &[&str], config: &Config) -> Result<(), Box<dyn Error>> {
+    let started = Instant::now();
+    let files = input::expand_inputs(input_file_paths)?;
+    tracing::info!(files = files.len(), "processing inputs");
+    let mut metrics = Metrics::default();
+    let mut statistics: Vec<FileStatistics> =
+        files.iter().map(|file| FileStatistics::new(file)).collect();
+    let mut engine = Engine::new(config.clone());
     let mut wtr = csv::Writer::from_writer(io::stdout());
+    let mut ledger = match &config.ledger {
+        Some(path) => Some(LedgerWriter::create(path)?),
+        None => None,
+    };
+    let mut journal = match &config.journal {
+        Some(path) => Some(JournalWriter::create(path)?),
+        None => None,
+    };
+    let mut checkpoints = match &config.checkpoints {
+        Some(checkpoint_config) => Some(CheckpointWriter::create(checkpoint_config, &files)?),
+        None => None,
+    };
+    let mut snapshots = match &config.snapshots {
+        Some(snapshot_config) => Some(SnapshotWriter::create(
+            snapshot_config,
+            config.fraud.is_enabled(),
+        )?),
+        None => None,
+    };
 
-    let file = tokio::fs::File::open(input_file_path).await.unwrap();
-    let mut rdr = AsyncReaderBuilder::new()
-        .trim(csv_async::Trim::All)
-        .flexible(true)
-        .create_deserializer(file);
-
-    let mut raw_record = csv_async::ByteRecord::new();
-    let headers = rdr.byte_headers().await?.clone();
+    let mut input = MergedInput::open(
+        &files,
+        config.input_order,
+        config.input_format,
+        &config.dialect,
+    )
+    .instrument(tracing::info_span!("open_inputs"))
+    .await?;
+    while let Some((file, transaction)) = input
+        .next()
+        .instrument(tracing::debug_span!("read_row"))
+        .await?
+    {
+        let _row = tracing::debug_span!(
+            "process_row",
+            row = engine.rows() + 1,
+            file = %files[file].display()
+        )
+        .entered();
+        if let Some(checkpoints) = &mut checkpoints {
+            checkpoints.before_transaction(&engine, transaction.timestamp)?;
+        }
+        let r#type = transaction.r#type;
+        let result = engine.process(transaction);
+        statistics[file].record(result.is_ok());
+        if config.metrics.is_some() {
+            metrics.record(r#type, result);
+        }
+        if let Some(ledger) = &mut ledger {
+            for entry in engine.drain_ledger() {
+                ledger.write(&entry)?;
+            }
+        }
+        if let Some(journal) = &mut journal {
+            for entry in engine.drain_journal() {
+                journal.write(&entry)?;
+            }
+        }
+        if let Some(snapshots) = &mut snapshots {
+            snapshots.after_transaction(&mut engine)?;
+        }
+    }
+    tracing::info!(
+        rows = engine.rows(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "processed inputs"
+    );
 
-    while rdr.read_byte_record(&mut raw_record).await? {
-        let transaction: Transaction = raw_record.deserialize(Some(&headers))?;
+    let _output = tracing::info_span!("write_output").entered();
+    if let Some(ledger) = ledger {
+        ledger.finish()?;
+    }
+    if let Some(journal) = journal {
+        journal.finish()?;
+    }
+    if let (Some(path), Some(journal)) = (&config.trial_balance, engine.journal()) {
+        journal.write_trial_balance(File::create(path)?)?;
+    }
+    let violations = engine.journal_violations();
+    if !violations.is_empty() {
+        return Err(format!(
+            "the journal doesn't match the client states:\n{}",
+            violations.join("\n")
+        )
+        .into());
+    }
+    let audit_violations = if config.audit {
+        engine.audit()
+    } else {
+        Vec::new()
+    };
+    if let Some(output) = &config.metrics {
+        metrics.finish(&engine, started.elapsed());
+        metrics.write(output)?;
+    }
 
-        match clients.get_mut(&transaction.client_id) {
-            Some(client) if !client.locked => {
-                execute_transaction(&transaction, client, &mut transactions)
-            }
-            None => {
-                let mut client = Client::new();
-                execute_transaction(&transaction, &mut client, &mut transactions);
-                clients.insert(transaction.client_id, client);
+    match &config.parquet_report {
+        #[cfg(feature = "parquet")]
+        Some(path) => {
+            let outputs: Vec<ClientOutput> = engine.into_outputs().collect();
+            columnar::write_report(path, &outputs, config.fraud.is_enabled())?;
+        }
+        #[cfg(not(feature = "parquet"))]
+        Some(_) => return Err("parquet reports require the parquet feature".into()),
+        None => {
+            for output in engine.into_outputs() {
+                wtr.serialize(output)?;
             }
-            _ => (),
+            wtr.flush()?;
+        }
+    }
+
+    if config.summary {
+        summary::print_summary(&statistics)?;
+    }
+    // reported after the output, so the client states are there to investigate the violations
+    if config.audit {
+        audit::write_report(&audit_violations, io::stderr())?;
+        if !audit_violations.is_empty() {
+            return Err(format!("audit found {} violations", audit_violations.len()).into());
+        }
+    }
+    Ok(())
+}
+
+/// Processes transactions as they arrive from the input, or from stdin as csv without input, and serves
+/// prometheus metrics on the address meanwhile. Rows that can't be parsed are reported to stderr and skipped.
+/// Prints the final client states once the input ends.
+pub async fn serve(
+    input_file_path: Option<&str>,
+    config: &Config,
+    metrics_address: SocketAddr,
+) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(metrics_address).await?;
+    eprintln!(
+        "serving metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    let metrics = Arc::new(Mutex::new(ServerMetrics::default()));
+    let server = tokio::spawn(serve::serve_metrics(listener, Arc::clone(&metrics)));
+
+    let mut reader = match input_file_path {
+        Some(path) => {
+            TransactionReader::open(Path::new(path), config.input_format, &config.dialect).await?
+        }
+        None => {
+            let stdin: InputStream = Box::new(tokio::io::stdin());
+            TransactionReader::csv(tokio::io::BufReader::new(stdin), &config.dialect).await?
+        }
+    };
+    let mut engine = Engine::new(config.clone()).with_ledger();
+    // a long running service skips rows it can't parse
+    while let Some(transaction) = reader
+        .next_skipping_invalid(|line, err| eprintln!("line {}: {}", line, err))
+        .await?
+    {
+        let (r#type, tx_id) = (transaction.r#type, transaction.tx_id);
+        let disputed_before = serve::is_disputed(&engine, tx_id);
+        let started = Instant::now();
+        let result = engine.process(transaction);
+        let latency = started.elapsed();
+        let entries: Vec<LedgerEntry> = engine.drain_ledger().collect();
+        let disputed = (disputed_before, serve::is_disputed(&engine, tx_id));
+        if let Ok(mut metrics) = metrics.lock() {
+            metrics.record(
+                r#type,
+                result,
+                &entries,
+                disputed,
+                engine.client_count(),
+                latency,
+            );
+        }
+    }
+    server.abort();
+
+    let mut wtr = csv::Writer::from_writer(io::stdout());
+    for output in engine.into_outputs() {
+        wtr.serialize(output)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Processes csv transactions from memory into the engine, like `core_logic` does for a file.
+/// Stops at the first row that can't be parsed. Used by the fuzz targets.
+pub async fn process_csv(
+    input: &[u8],
+    dialect: &CsvDialect,
+    engine: &mut Engine,
+) -> Result<(), Box<dyn Error>> {
+    let stream: InputStream = Box::new(io::Cursor::new(input.to_vec()));
+    let mut reader = TransactionReader::csv(tokio::io::BufReader::new(stream), dialect).await?;
+    while let Some(transaction) = reader.next().await? {
+        let _ = engine.process(transaction);
+    }
+    Ok(())
+}
+
+/// Reconstructs the state of a single client at the target by replaying the input from the nearest checkpoint.
+/// Outputs only the header if the client didn't exist yet.
+pub async fn query_client(
+    input_file_path: &str,
+    config: &Config,
+    checkpoint_dir: &Path,
+    client_id: ClientId,
+    target: QueryTarget,
+) -> Result<(), Box<dyn Error>> {
+    let mut engine = match checkpoint::load_nearest(checkpoint_dir, target)? {
+        Some(checkpoint) => {
+            checkpoint.check_input(Path::new(input_file_path))?;
+            Engine::from_checkpoint(config.clone(), checkpoint)
+        }
+        None => Engine::new(config.clone()),
+    };
+
+    let mut reader = TransactionReader::open(
+        Path::new(input_file_path),
+        config.input_format,
+        &config.dialect,
+    )
+    .await?;
+    reader.skip(engine.rows()).await?;
+    while let Some(transaction) = reader.next().await? {
+        if !target.includes(engine.rows() + 1, transaction.timestamp) {
+            break;
+        }
+        // rejected transactions leave the state unchanged, like in core_logic
+        let _ = engine.process(transaction);
+    }
+
+    write_client_output(&engine, config, client_id)
+}
+
+/// Replays the input and prints every transaction of the client with its outcome and the balances after it,
+/// followed by the final state of the client
+pub async fn explain_client(
+    input_file_path: &str,
+    config: &Config,
+    client_id: ClientId,
+) -> Result<(), Box<dyn Error>> {
+    // transactions of other clients are processed too, disputes can reference their deposits
+    let mut engine = Engine::new(config.clone()).with_ledger();
+    let mut reader = TransactionReader::open(
+        Path::new(input_file_path),
+        config.input_format,
+        &config.dialect,
+    )
+    .await?;
+
+    let mut wtr = csv_writer_with_header(io::stdout(), STEP_HEADERS)?;
+    while let Some(transaction) = reader.next().await? {
+        if transaction.client_id == client_id {
+            wtr.serialize(Step::process(&mut engine, transaction))?;
+        } else {
+            let _ = engine.process(transaction);
+            engine.drain_ledger().for_each(drop);
         }
     }
+    wtr.flush()?;
+
+    println!();
+    write_client_output(&engine, config, client_id)
+}
+
+/// Parses the whole input and processes it without output of balances, then prints the problems found per category
+/// with their line numbers. Fails if there were any problems.
+pub async fn validate_input(input_file_path: &str, config: &Config) -> Result<(), Box<dyn Error>> {
+    let mut validator = Validator::new(config.clone());
+    let mut reader = TransactionReader::open(
+        Path::new(input_file_path),
+        config.input_format,
+        &config.dialect,
+    )
+    .await?;
 
-    for (id, client) in clients {
-        wtr.serialize(ClientOutput::from_client(client, id))?;
+    let mut rows = 0;
+    // parse errors are reported and skipped
+    while let Some(transaction) = reader
+        .next_skipping_invalid(|line, _| {
+            rows += 1;
+            validator.schema_error(line);
+        })
+        .await?
+    {
+        rows += 1;
+        validator.check(transaction, reader.line().unwrap_or(rows));
     }
 
+    validator.write_summary(io::stdout())?;
+    match validator.problem_count() {
+        0 => Ok(()),
+        problems => Err(format!("found {} problems in {} rows", problems, rows).into()),
+    }
+}
+
+/// Compares two client reports by client id, amounts are equal if their values are equal regardless of their scale.
+/// Prints the clients missing from either report and every differing field, fails if there were any differences.
+pub fn diff_reports(expected: &Path, actual: &Path) -> Result<(), Box<dyn Error>> {
+    let differences = diff::differences(&Report::load(expected)?, &Report::load(actual)?);
+
+    let mut wtr = csv_writer_with_header(
+        io::stdout(),
+        ["client", "problem", "field", "expected", "actual"],
+    )?;
+    for difference in &differences {
+        wtr.serialize(difference)?;
+    }
     wtr.flush()?;
+    match differences.len() {
+        0 => Ok(()),
+        count => Err(format!("found {} differences", count).into()),
+    }
+}
+
+/// Prints the state of the client as csv, only the header if the client doesn't exist
+fn write_client_output(
+    engine: &Engine,
+    config: &Config,
+    client_id: ClientId,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(io::stdout());
+    match engine.client_output(client_id) {
+        Some(output) => wtr.serialize(output)?,
+        None => wtr.write_record(ClientOutput::headers(config.fraud.is_enabled()))?,
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Creates a csv writer that has written the header already. Serializing writes the header only with the first
+/// record, this way it is there even if nothing is written.
+pub(crate) fn csv_writer_with_header<W: io::Write, I, T>(
+    writer: W,
+    header: I,
+) -> Result<csv::Writer<W>, csv::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    wtr.write_record(header)?;
+    Ok(wtr)
+}
+
+/// Writes deterministic test transactions as csv, and the expected final report if configured
+pub fn generate(config: &GeneratorConfig, writer: impl io::Write) -> Result<(), Box<dyn Error>> {
+    let mut generator = Generator::new(config)?;
+    generate::write_transactions(&mut generator, config.rows, writer)?;
+    if let Some(path) = &config.report {
+        generator.write_report(File::create(path)?)?;
+    }
     Ok(())
 }
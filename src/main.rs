@@ -1,24 +1,610 @@
-use clap::{App, Arg};
-use engine_lib::core_logic;
+use clap::{App, Arg, ArgGroup, ArgMatches};
+use engine_lib::{
+    core_logic, diff_reports, explain_client, generate, query_client, serve, validate_input,
+    CheckpointConfig, CheckpointInterval, Config, CsvDialect, FraudThresholds, GeneratorConfig,
+    InputFormat, InputOrder, MetricsOutput, QueryTarget, SnapshotConfig, SnapshotInterval,
+    TransactionType,
+};
+use rust_decimal::Decimal;
 use std::error::Error;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let matches = App::new("Transaction Engine")
         .version("0.1")
         .author("do4gr")
-        .about("Processes transactions input as csv, outputs client account states to stdout.")
+        .about("Processes transactions input as csv or json lines, outputs client account states to stdout.")
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .arg(
-            Arg::new("INPUT")
-                .about("Sets the input file to use")
-                .required(true)
-                .index(1),
+            input_arg()
+                .help("Sets the input files to use, directories and glob patterns are expanded")
+                .multiple_values(true),
+        )
+        .args(engine_args())
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(["text", "json"])
+                .default_value("text")
+                .global(true)
+                .help("Format of the logs on stderr, RUST_LOG selects what is logged, e.g. RUST_LOG=engine_lib=debug"),
+        )
+        .arg(
+            Arg::new("order")
+                .long("order")
+                .takes_value(true)
+                .possible_values(["sequential", "timestamp", "tx"])
+                .default_value("sequential")
+                .help("Processes several inputs one after the other or merges them by the timestamp or tx column"),
+        )
+        .arg(
+            Arg::new("summary")
+                .long("summary")
+                .help("Prints statistics of every input file to stderr"),
+        )
+        .arg(
+            Arg::new("metrics")
+                .long("metrics")
+                .help("Prints counts per type and rejection reason, volumes and throughput of the whole run to stderr"),
+        )
+        .arg(
+            Arg::new("metrics-json")
+                .long("metrics-json")
+                .takes_value(true)
+                .value_name("FILE")
+                .conflicts_with("metrics")
+                .help("Writes the metrics of the whole run to FILE as json"),
+        )
+        .arg(
+            Arg::new("audit")
+                .long("audit")
+                .help("Verifies the client totals and held funds against the accepted transactions and archived deposits after processing, reports violations to stderr and fails"),
+        )
+        .arg(
+            Arg::new("parquet-report")
+                .long("parquet-report")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes the client states to FILE as parquet instead of csv to stdout"),
+        )
+        .arg(
+            Arg::new("ledger")
+                .long("ledger")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Exports every balance change to FILE, as json for .json files and csv otherwise"),
+        )
+        .arg(
+            Arg::new("journal")
+                .long("journal")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Exports every balance change as double-entry journal lines to FILE as csv"),
+        )
+        .arg(
+            Arg::new("trial-balance")
+                .long("trial-balance")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes the trial balance of the double-entry journal to FILE as csv and checks it against the client states"),
+        )
+        .arg(
+            Arg::new("checkpoint-dir")
+                .long("checkpoint-dir")
+                .takes_value(true)
+                .value_name("DIR")
+                .help("Writes checkpoints of all client states to DIR for later queries"),
+        )
+        .arg(
+            Arg::new("checkpoint-every")
+                .long("checkpoint-every")
+                .takes_value(true)
+                .value_name("ROWS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .requires("checkpoint-dir")
+                .conflicts_with("checkpoint-on-timestamp")
+                .help("Writes a checkpoint every ROWS rows [default: 100000]"),
+        )
+        .arg(
+            Arg::new("checkpoint-on-timestamp")
+                .long("checkpoint-on-timestamp")
+                .requires("checkpoint-dir")
+                .help("Writes a checkpoint whenever the timestamp column changes"),
+        )
+        .arg(
+            Arg::new("snapshot-file")
+                .long("snapshot-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Writes intermediate client states to FILE while processing, - for stderr"),
+        )
+        .arg(
+            Arg::new("snapshot-every")
+                .long("snapshot-every")
+                .takes_value(true)
+                .value_name("ROWS")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .requires("snapshot-file")
+                .conflicts_with("snapshot-every-secs")
+                .help("Writes a snapshot every ROWS rows [default: 100000]"),
+        )
+        .arg(
+            Arg::new("snapshot-every-secs")
+                .long("snapshot-every-secs")
+                .takes_value(true)
+                .value_name("SECONDS")
+                .value_parser(positive_seconds)
+                .requires("snapshot-file")
+                .help("Writes a snapshot every SECONDS seconds"),
+        )
+        .arg(
+            Arg::new("snapshot-changed-only")
+                .long("snapshot-changed-only")
+                .requires("snapshot-file")
+                .help("Only writes clients that changed since the previous snapshot"),
+        )
+        .subcommand(
+            App::new("query")
+                .about("Outputs the state of a client at a given row or timestamp of the input, replaying from the nearest checkpoint")
+                .arg(input_arg())
+                .args(engine_args())
+                .arg(
+                    Arg::new("checkpoint-dir")
+                        .long("checkpoint-dir")
+                        .takes_value(true)
+                        .value_name("DIR")
+                        .required(true)
+                        .help("Directory the checkpoints of the input were written to"),
+                )
+                .arg(
+                    Arg::new("client")
+                        .long("client")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Id of the client to query"),
+                )
+                .arg(
+                    Arg::new("row")
+                        .long("row")
+                        .takes_value(true)
+                        .help("Balances after this row of the input, counted from 1"),
+                )
+                .arg(
+                    Arg::new("timestamp")
+                        .long("timestamp")
+                        .takes_value(true)
+                        .help("Balances after all rows up to this timestamp"),
+                )
+                .group(
+                    ArgGroup::new("target")
+                        .args(&["row", "timestamp"])
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("explain")
+                .about("Prints every transaction of a client with its outcome and the balances after it")
+                .arg(input_arg())
+                .args(engine_args())
+                .arg(
+                    Arg::new("client")
+                        .long("client")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Id of the client to explain"),
+                ),
+        )
+        .subcommand(
+            App::new("validate")
+                .about("Checks the input for schema errors, invalid amounts, duplicate transaction ids and invalid references without printing balances")
+                .arg(input_arg())
+                .args(engine_args()),
+        )
+        .subcommand(
+            App::new("serve")
+                .about("Processes transactions as they arrive and serves prometheus metrics, prints the client states once the input ends")
+                .arg(
+                    input_arg()
+                        .required(false)
+                        .help("File or named pipe to read from, csv from stdin if not given"),
+                )
+                .args(engine_args())
+                .arg(
+                    Arg::new("metrics-address")
+                        .long("metrics-address")
+                        .takes_value(true)
+                        .value_name("ADDRESS")
+                        .default_value("127.0.0.1:9898")
+                        .help("Address to serve the prometheus metrics on at /metrics, port 0 picks a free port"),
+                ),
+        )
+        .subcommand(
+            App::new("diff")
+                .about("Compares two account reports by client id and prints missing clients and differing fields")
+                .arg(
+                    Arg::new("EXPECTED")
+                        .required(true)
+                        .help("Report with the expected balances, for example from the bank"),
+                )
+                .arg(
+                    Arg::new("ACTUAL")
+                        .required(true)
+                        .help("Report to check, for example the output of the engine"),
+                ),
+        )
+        .subcommand(
+            App::new("generate")
+                .about("Writes deterministic test transactions as csv to stdout")
+                .arg(
+                    Arg::new("seed")
+                        .long("seed")
+                        .takes_value(true)
+                        .default_value("0")
+                        .help("Seed of the random generator, the same options always generate the same transactions"),
+                )
+                .arg(
+                    Arg::new("rows")
+                        .long("rows")
+                        .takes_value(true)
+                        .default_value("100000")
+                        .help("Number of transactions"),
+                )
+                .arg(
+                    Arg::new("clients")
+                        .long("clients")
+                        .takes_value(true)
+                        .default_value("10000")
+                        .help("Number of clients, they get the ids 1 to CLIENTS"),
+                )
+                .arg(
+                    Arg::new("weight")
+                        .long("weight")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("TYPE=WEIGHT")
+                        .help("Relative frequency of a transaction type, defaults: deposit=16 withdrawal=14 dispute=7 resolve=2 chargeback=1 reversal=0"),
+                )
+                .arg(
+                    Arg::new("valid-only")
+                        .long("valid-only")
+                        .help("Only generate transactions the engine applies, by tracking the balances of all clients"),
+                )
+                .arg(
+                    Arg::new("report")
+                        .long("report")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .requires("valid-only")
+                        .help("Also write the expected final state of all clients to this csv file"),
+                ),
         )
         .get_matches();
+    init_tracing(matches.value_of("log-format") == Some("json"));
+
+    if let Some(matches) = matches.subcommand_matches("generate") {
+        let config = GeneratorConfig {
+            seed: matches.value_of_t("seed")?,
+            rows: matches.value_of_t("rows")?,
+            clients: matches.value_of_t("clients")?,
+            weights: weights_from(matches)?,
+            valid_only: matches.is_present("valid-only"),
+            report: matches.value_of("report").map(PathBuf::from),
+        };
+        generate(&config, std::io::stdout())?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("serve") {
+        let config = config_from(matches)?;
+        serve(
+            matches.value_of("INPUT"),
+            &config,
+            matches.value_of_t("metrics-address")?,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("diff") {
+        diff_reports(
+            Path::new(matches.value_of("EXPECTED").unwrap_or_default()),
+            Path::new(matches.value_of("ACTUAL").unwrap_or_default()),
+        )?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("explain") {
+        let config = config_from(matches)?;
+        explain_client(
+            matches.value_of("INPUT").unwrap_or_default(),
+            &config,
+            matches.value_of_t("client")?,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("validate") {
+        let config = config_from(matches)?;
+        validate_input(matches.value_of("INPUT").unwrap_or_default(), &config).await?;
+        return Ok(());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("query") {
+        let config = config_from(matches)?;
+        let target = match optional_value(matches, "row")? {
+            Some(row) => QueryTarget::Row(row),
+            None => QueryTarget::Timestamp(matches.value_of_t("timestamp")?),
+        };
+        let input_file_path = matches.value_of("INPUT").unwrap_or_default();
+        let checkpoint_dir = Path::new(matches.value_of("checkpoint-dir").unwrap_or_default());
+        query_client(
+            input_file_path,
+            &config,
+            checkpoint_dir,
+            matches.value_of_t("client")?,
+            target,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut config = config_from(&matches)?;
+    config.ledger = matches.value_of("ledger").map(PathBuf::from);
+    config.journal = matches.value_of("journal").map(PathBuf::from);
+    config.audit = matches.is_present("audit");
+    config.trial_balance = matches.value_of("trial-balance").map(PathBuf::from);
+    config.parquet_report = matches.value_of("parquet-report").map(PathBuf::from);
+    config.checkpoints = matches
+        .value_of("checkpoint-dir")
+        .map(|dir| CheckpointConfig {
+            dir: PathBuf::from(dir),
+            interval: if matches.is_present("checkpoint-on-timestamp") {
+                CheckpointInterval::Timestamp
+            } else {
+                CheckpointInterval::Rows(
+                    matches
+                        .get_one::<u64>("checkpoint-every")
+                        .copied()
+                        .unwrap_or(100_000),
+                )
+            },
+        });
+
+    config.snapshots = matches
+        .value_of("snapshot-file")
+        .map(|path| SnapshotConfig {
+            path: PathBuf::from(path),
+            interval: match matches.get_one::<Duration>("snapshot-every-secs") {
+                Some(interval) => SnapshotInterval::Time(*interval),
+                None => SnapshotInterval::Rows(
+                    matches
+                        .get_one::<u64>("snapshot-every")
+                        .copied()
+                        .unwrap_or(100_000),
+                ),
+            },
+            changed_only: matches.is_present("snapshot-changed-only"),
+        });
 
-    if let Some(input_file_path) = matches.value_of("INPUT") {
-        core_logic(input_file_path).await?
+    config.input_order = match matches.value_of("order") {
+        Some("timestamp") => InputOrder::Timestamp,
+        Some("tx") => InputOrder::TxId,
+        _ => InputOrder::Sequential,
+    };
+    config.summary = matches.is_present("summary");
+    config.metrics = if matches.is_present("metrics") {
+        Some(MetricsOutput::Stderr)
+    } else {
+        matches
+            .value_of("metrics-json")
+            .map(|path| MetricsOutput::Json(PathBuf::from(path)))
+    };
+
+    if let Some(input_file_paths) = matches.values_of("INPUT") {
+        let input_file_paths: Vec<&str> = input_file_paths.collect();
+        core_logic(&input_file_paths, &config).await?
     }
 
     Ok(())
 }
+
+/// Logs to stderr, stdout is reserved for the client states. Without RUST_LOG only errors are logged.
+fn init_tracing(json: bool) {
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    if json {
+        subscriber.json().init();
+    } else {
+        subscriber.init();
+    }
+}
+
+fn input_arg() -> Arg<'static> {
+    Arg::new("INPUT")
+        .help("Sets the input file to use")
+        .required(true)
+        .index(1)
+}
+
+/// Options that change how transactions are processed, shared by all commands replaying the input
+fn engine_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("flag-dispute-ratio")
+            .long("flag-dispute-ratio")
+            .takes_value(true)
+            .value_parser(ratio)
+            .help("Flags clients whose ratio of disputes to deposits exceeds this value"),
+        Arg::new("lock-dispute-ratio")
+            .long("lock-dispute-ratio")
+            .takes_value(true)
+            .value_parser(ratio)
+            .help("Locks clients whose ratio of disputes to deposits exceeds this value"),
+        Arg::new("flag-chargeback-ratio")
+            .long("flag-chargeback-ratio")
+            .takes_value(true)
+            .value_parser(ratio)
+            .help("Flags clients whose ratio of chargebacks to deposits exceeds this value"),
+        Arg::new("lock-chargeback-ratio")
+            .long("lock-chargeback-ratio")
+            .takes_value(true)
+            .value_parser(ratio)
+            .help("Locks clients whose ratio of chargebacks to deposits exceeds this value"),
+        Arg::new("fraud-min-deposits")
+            .long("fraud-min-deposits")
+            .takes_value(true)
+            .default_value("1")
+            .help("Number of deposits a client needs before fraud ratios are evaluated"),
+        Arg::new("unlock-on-reversal")
+            .long("unlock-on-reversal")
+            .help("Unlocks clients once all of their chargebacks were reversed"),
+        Arg::new("format")
+            .long("format")
+            .takes_value(true)
+            .possible_values(["auto", "csv", "jsonl", "parquet"])
+            .default_value("auto")
+            .help("Format of the input files, auto detects it by extension or the first character"),
+        Arg::new("delimiter")
+            .long("delimiter")
+            .takes_value(true)
+            .value_name("CHAR")
+            .default_value(",")
+            .help("Delimiter of csv inputs, \\t for tabs"),
+        Arg::new("quote")
+            .long("quote")
+            .takes_value(true)
+            .value_name("CHAR")
+            .default_value("\"")
+            .help("Quote character of csv inputs"),
+        Arg::new("no-headers")
+            .long("no-headers")
+            .help("Csv inputs have no header row, columns are read in the order type, client, tx, amount, timestamp"),
+        Arg::new("column")
+            .long("column")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .value_name("FROM=TO")
+            .help("Reads the csv column FROM as TO, e.g. client_id=client"),
+        Arg::new("case-insensitive-types")
+            .long("case-insensitive-types")
+            .help("Accepts transaction types in any case, e.g. DEPOSIT"),
+        Arg::new("decimal-comma")
+            .long("decimal-comma")
+            .help("Amounts of csv inputs use a comma as decimal separator"),
+    ]
+}
+
+fn config_from(matches: &ArgMatches) -> Result<Config, clap::Error> {
+    Ok(Config {
+        fraud: FraudThresholds {
+            min_deposits: matches.value_of_t("fraud-min-deposits")?,
+            flag_dispute_ratio: matches.get_one::<Decimal>("flag-dispute-ratio").copied(),
+            lock_dispute_ratio: matches.get_one::<Decimal>("lock-dispute-ratio").copied(),
+            flag_chargeback_ratio: matches.get_one::<Decimal>("flag-chargeback-ratio").copied(),
+            lock_chargeback_ratio: matches.get_one::<Decimal>("lock-chargeback-ratio").copied(),
+        },
+        unlock_on_reversal: matches.is_present("unlock-on-reversal"),
+        dialect: dialect_from(matches)?,
+        input_format: match matches.value_of("format") {
+            Some("csv") => InputFormat::Csv,
+            Some("jsonl") => InputFormat::JsonLines,
+            Some("parquet") => InputFormat::Parquet,
+            _ => InputFormat::Auto,
+        },
+        ..Config::default()
+    })
+}
+
+fn dialect_from(matches: &ArgMatches) -> Result<CsvDialect, clap::Error> {
+    let mut columns = Vec::new();
+    for mapping in matches.values_of("column").into_iter().flatten() {
+        match mapping.split_once('=') {
+            Some((from, to)) => columns.push((from.to_string(), to.to_string())),
+            None => {
+                return Err(clap::Error::raw(
+                    clap::ErrorKind::InvalidValue,
+                    format!("--column expects FROM=TO, got {}\n", mapping),
+                ))
+            }
+        }
+    }
+
+    Ok(CsvDialect {
+        delimiter: single_byte(matches, "delimiter")?,
+        quote: single_byte(matches, "quote")?,
+        has_headers: !matches.is_present("no-headers"),
+        columns,
+        case_insensitive_types: matches.is_present("case-insensitive-types"),
+        decimal_comma: matches.is_present("decimal-comma"),
+    })
+}
+
+/// Overrides the default weights with the `--weight TYPE=WEIGHT` arguments
+fn weights_from(matches: &ArgMatches) -> Result<Vec<(TransactionType, u32)>, clap::Error> {
+    let mut weights = GeneratorConfig::default().weights;
+    for argument in matches.values_of("weight").into_iter().flatten() {
+        let invalid = || {
+            clap::Error::raw(
+                clap::ErrorKind::InvalidValue,
+                format!(
+                    "--weight expects TYPE=WEIGHT with a transaction type, got {}\n",
+                    argument
+                ),
+            )
+        };
+        let (name, weight) = argument.split_once('=').ok_or_else(invalid)?;
+        let weight: u32 = weight.parse().map_err(|_| invalid())?;
+        match weights.iter_mut().find(|(r#type, _)| r#type.name() == name) {
+            Some((_, current)) => *current = weight,
+            None => return Err(invalid()),
+        }
+    }
+    Ok(weights)
+}
+
+fn single_byte(matches: &ArgMatches, name: &str) -> Result<u8, clap::Error> {
+    match matches.value_of(name).unwrap_or_default().as_bytes() {
+        [byte] => Ok(*byte),
+        b"\\t" => Ok(b'\t'),
+        _ => Err(clap::Error::raw(
+            clap::ErrorKind::InvalidValue,
+            format!("--{} expects a single ascii character\n", name),
+        )),
+    }
+}
+
+fn ratio(value: &str) -> Result<Decimal, String> {
+    let ratio: Decimal = value.parse().map_err(|err| format!("{}", err))?;
+    if ratio < Decimal::ZERO || ratio > Decimal::ONE {
+        return Err("expected a ratio between 0 and 1".to_string());
+    }
+    Ok(ratio)
+}
+
+fn positive_seconds(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value.parse().map_err(|err| format!("{}", err))?;
+    if !(seconds.is_finite() && seconds > 0.0) {
+        return Err("expected a finite number of seconds greater than 0".to_string());
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|err| format!("{}", err))
+}
+
+fn optional_value<T>(matches: &ArgMatches, name: &str) -> Result<Option<T>, clap::Error>
+where
+    T: std::str::FromStr,
+    <T as std::str::FromStr>::Err: std::fmt::Display,
+{
+    if matches.is_present(name) {
+        matches.value_of_t(name).map(Some)
+    } else {
+        Ok(None)
+    }
+}
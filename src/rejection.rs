@@ -0,0 +1,64 @@
+use crate::archived_deposit::TransitionError;
+use std::fmt;
+
+/// Reasons for a transaction to be ignored, the state of the client is unchanged
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    /// deposits and withdrawals need an amount
+    MissingAmount,
+    /// deposits and withdrawals with negative amounts are ignored
+    NegativeAmount,
+    /// deposits and withdrawals above 10^15 are ignored, so balances can't overflow
+    AmountTooLarge,
+    /// a deposit reuses the transaction id of an archived deposit
+    DuplicateTx,
+    /// a withdrawal exceeds the available funds
+    InsufficientFunds,
+    /// disputes, resolves, chargebacks and reversals have to reference an archived deposit
+    UnknownDeposit,
+    /// the referenced deposit rejected the transition
+    InvalidTransition(TransitionError),
+    /// only reversals are accepted for locked clients
+    ClientLocked,
+}
+
+impl Rejection {
+    /// snake case name to group rejections by
+    pub fn category(&self) -> &'static str {
+        match self {
+            Rejection::MissingAmount => "missing_amount",
+            Rejection::NegativeAmount => "negative_amount",
+            Rejection::AmountTooLarge => "amount_too_large",
+            Rejection::DuplicateTx => "duplicate_tx",
+            Rejection::InsufficientFunds => "insufficient_funds",
+            Rejection::UnknownDeposit => "unknown_deposit",
+            Rejection::InvalidTransition(TransitionError::InvalidState(_)) => "invalid_state",
+            Rejection::InvalidTransition(TransitionError::InvalidAmount) => "invalid_amount",
+            Rejection::InvalidTransition(TransitionError::ClientMismatch) => "client_mismatch",
+            Rejection::ClientLocked => "client_locked",
+        }
+    }
+}
+
+impl From<TransitionError> for Rejection {
+    fn from(error: TransitionError) -> Self {
+        Rejection::InvalidTransition(error)
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::MissingAmount => write!(f, "missing amount"),
+            Rejection::NegativeAmount => write!(f, "negative amount"),
+            Rejection::AmountTooLarge => write!(f, "amount above the maximum of 10^15"),
+            Rejection::DuplicateTx => {
+                write!(f, "a deposit with this transaction id exists already")
+            }
+            Rejection::InsufficientFunds => write!(f, "insufficient available funds"),
+            Rejection::UnknownDeposit => write!(f, "no deposit with this transaction id"),
+            Rejection::InvalidTransition(error) => write!(f, "{}", error),
+            Rejection::ClientLocked => write!(f, "client is locked"),
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use crate::ledger::{LedgerEntry, LedgerEvent};
+use crate::rejection::Rejection;
+use crate::transaction::{TransactionType, TxId};
+use crate::Engine;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Upper bounds of the latency histogram buckets in seconds
+const LATENCY_BUCKETS: [f64; 8] = [1e-6, 5e-6, 1e-5, 5e-5, 1e-4, 5e-4, 1e-3, 1e-2];
+
+/// Metrics of a running server in the form prometheus expects them.
+/// The gauges are updated with every transaction, so scraping doesn't have to look at all clients.
+#[derive(Debug, Default)]
+pub(crate) struct ServerMetrics {
+    /// by type and outcome, the outcome is `applied` or the category of the rejection
+    transactions: BTreeMap<(&'static str, &'static str), u64>,
+    clients: usize,
+    locked_clients: i64,
+    /// deposits with a disputed amount that is neither resolved nor charged back
+    open_disputes: i64,
+    held: Decimal,
+    /// count of every latency bucket, not cumulative
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_count: u64,
+    latency_sum: f64,
+}
+
+impl ServerMetrics {
+    /// Records a processed transaction with the ledger entries it caused and whether its deposit
+    /// was disputed before and after it
+    pub(crate) fn record(
+        &mut self,
+        r#type: TransactionType,
+        result: Result<(), Rejection>,
+        entries: &[LedgerEntry],
+        disputed: (bool, bool),
+        clients: usize,
+        latency: Duration,
+    ) {
+        let outcome = match result {
+            Ok(()) => "applied",
+            Err(rejection) => rejection.category(),
+        };
+        *self
+            .transactions
+            .entry((r#type.name(), outcome))
+            .or_default() += 1;
+
+        for entry in entries {
+            match entry.event {
+                LedgerEvent::MoveToHeld(amount) => self.held += amount,
+                LedgerEvent::ReleaseHeld(amount) | LedgerEvent::DebitHeld(amount) => {
+                    self.held -= amount
+                }
+                LedgerEvent::Lock => self.locked_clients += 1,
+                LedgerEvent::Unlock => self.locked_clients -= 1,
+                LedgerEvent::CreditAvailable(_) | LedgerEvent::DebitAvailable(_) => {}
+            }
+        }
+        self.open_disputes += i64::from(disputed.1) - i64::from(disputed.0);
+        self.clients = clients;
+
+        let seconds = latency.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.latency_buckets[bucket] += 1;
+        }
+        self.latency_count += 1;
+        self.latency_sum += seconds;
+    }
+
+    /// Renders all metrics in the prometheus text format
+    pub(crate) fn render(&self) -> String {
+        let mut text = String::new();
+        // writing to a string can't fail
+        let _ = self.render_into(&mut text);
+        text
+    }
+
+    fn render_into(&self, text: &mut String) -> std::fmt::Result {
+        writeln!(
+            text,
+            "# HELP engine_transactions_total Processed transactions by type and outcome."
+        )?;
+        writeln!(text, "# TYPE engine_transactions_total counter")?;
+        for ((r#type, outcome), count) in &self.transactions {
+            writeln!(
+                text,
+                "engine_transactions_total{{type=\"{}\",outcome=\"{}\"}} {}",
+                r#type, outcome, count
+            )?;
+        }
+
+        let gauges = [
+            (
+                "engine_clients",
+                "Clients with at least one transaction.",
+                self.clients.to_string(),
+            ),
+            (
+                "engine_locked_clients",
+                "Locked clients.",
+                self.locked_clients.to_string(),
+            ),
+            (
+                "engine_open_disputes",
+                "Deposits with an open dispute.",
+                self.open_disputes.to_string(),
+            ),
+            (
+                "engine_held_funds",
+                "Sum of the held funds of all clients.",
+                self.held.to_f64().unwrap_or_default().to_string(),
+            ),
+        ];
+        for (name, help, value) in &gauges {
+            writeln!(text, "# HELP {} {}", name, help)?;
+            writeln!(text, "# TYPE {} gauge", name)?;
+            writeln!(text, "{} {}", name, value)?;
+        }
+
+        let name = "engine_processing_seconds";
+        writeln!(
+            text,
+            "# HELP {} Time to process a single transaction.",
+            name
+        )?;
+        writeln!(text, "# TYPE {} histogram", name)?;
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&self.latency_buckets) {
+            cumulative += count;
+            writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative)?;
+        }
+        writeln!(
+            text,
+            "{}_bucket{{le=\"+Inf\"}} {}",
+            name, self.latency_count
+        )?;
+        writeln!(text, "{}_sum {}", name, self.latency_sum)?;
+        writeln!(text, "{}_count {}", name, self.latency_count)
+    }
+}
+
+/// Whether the deposit with the id has a disputed amount
+pub(crate) fn is_disputed(engine: &Engine, tx_id: TxId) -> bool {
+    engine
+        .deposit(tx_id)
+        .is_some_and(|deposit| !deposit.disputed().is_zero())
+}
+
+/// Answers `GET /metrics` with the metrics and everything else with 404, until the process ends
+pub(crate) async fn serve_metrics(
+    listener: TcpListener,
+    metrics: Arc<Mutex<ServerMetrics>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            // a failing scrape must not stop the server
+            let _ = respond(stream, &metrics).await;
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, metrics: &Mutex<ServerMetrics>) -> std::io::Result<()> {
+    // only the request line is of interest, but the headers are read to their end so closing the connection
+    // doesn't reset it while the client is still sending. Bodies are ignored, longer requests are cut off.
+    let mut request = vec![0; 4096];
+    let mut read = 0;
+    while read < request.len() && !request[..read].windows(4).any(|end| end == b"\r\n\r\n") {
+        match stream.read(&mut request[read..]).await? {
+            0 => break,
+            bytes => read += bytes,
+        }
+    }
+    let request = String::from_utf8_lossy(&request[..read]);
+
+    let response = if request.starts_with("GET /metrics ") {
+        let body = metrics
+            .lock()
+            .map(|metrics| metrics.render())
+            .unwrap_or_default();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ledger::{LedgerEntry, LedgerEvent};
+    use crate::rejection::Rejection;
+    use crate::serve::ServerMetrics;
+    use crate::transaction::TransactionType;
+    use rust_decimal::Decimal;
+    use std::time::Duration;
+
+    fn entry(event: LedgerEvent) -> LedgerEntry {
+        LedgerEntry {
+            row: 1,
+            client_id: 1,
+            tx_id: 1,
+            event,
+            available: Decimal::ZERO,
+            held: Decimal::ZERO,
+            total: Decimal::ZERO,
+            locked: false,
+        }
+    }
+
+    #[test]
+    fn render_counters_gauges_and_histogram() {
+        let mut metrics = ServerMetrics::default();
+        let micros = Duration::from_micros;
+        metrics.record(
+            TransactionType::Deposit,
+            Ok(()),
+            &[entry(LedgerEvent::CreditAvailable(Decimal::new(5, 0)))],
+            (false, false),
+            1,
+            micros(3),
+        );
+        metrics.record(
+            TransactionType::Dispute,
+            Ok(()),
+            &[entry(LedgerEvent::MoveToHeld(Decimal::new(5, 0)))],
+            (false, true),
+            1,
+            micros(20),
+        );
+        metrics.record(
+            TransactionType::Chargeback,
+            Ok(()),
+            &[
+                entry(LedgerEvent::DebitHeld(Decimal::new(2, 0))),
+                entry(LedgerEvent::Lock),
+            ],
+            (true, true),
+            1,
+            micros(20),
+        );
+        metrics.record(
+            TransactionType::Deposit,
+            Err(Rejection::ClientLocked),
+            &[],
+            (false, false),
+            1,
+            Duration::from_secs(1),
+        );
+
+        let text = metrics.render();
+        assert!(
+            text.contains("engine_transactions_total{type=\"deposit\",outcome=\"applied\"} 1\n")
+        );
+        assert!(text
+            .contains("engine_transactions_total{type=\"deposit\",outcome=\"client_locked\"} 1\n"));
+        assert!(text.contains("engine_clients 1\n"));
+        assert!(text.contains("engine_locked_clients 1\n"));
+        assert!(text.contains("engine_open_disputes 1\n"));
+        assert!(text.contains("engine_held_funds 3\n"));
+        assert!(text.contains("engine_processing_seconds_bucket{le=\"0.000005\"} 1\n"));
+        assert!(text.contains("engine_processing_seconds_bucket{le=\"0.00005\"} 3\n"));
+        assert!(text.contains("engine_processing_seconds_bucket{le=\"0.01\"} 3\n"));
+        assert!(text.contains("engine_processing_seconds_bucket{le=\"+Inf\"} 4\n"));
+        assert!(text.contains("engine_processing_seconds_count 4\n"));
+    }
+}
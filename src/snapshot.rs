@@ -0,0 +1,86 @@
+use crate::client::ClientOutput;
+use crate::{csv_writer_with_header, Engine};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// When intermediate client states are emitted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotInterval {
+    Rows(u64),
+    Time(Duration),
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotConfig {
+    /// file the snapshots are appended to, `-` for stderr
+    pub path: PathBuf,
+    pub interval: SnapshotInterval,
+    /// only emit clients that changed since the previous snapshot
+    pub changed_only: bool,
+}
+
+/// Writes snapshots as csv, every row is prefixed with the number of the snapshot and the rows processed so far
+pub(crate) struct SnapshotWriter {
+    config: SnapshotConfig,
+    wtr: csv::Writer<Box<dyn Write>>,
+    snapshots: u64,
+    last_snapshot: Instant,
+}
+
+impl SnapshotWriter {
+    pub(crate) fn create(
+        config: &SnapshotConfig,
+        with_flags: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let sink: Box<dyn Write> = if config.path.as_os_str() == "-" {
+            Box::new(io::stderr())
+        } else {
+            Box::new(File::create(&config.path)?)
+        };
+        let wtr = csv_writer_with_header(
+            sink,
+            ["snapshot", "row"]
+                .iter()
+                .chain(ClientOutput::headers(with_flags)),
+        )?;
+
+        Ok(SnapshotWriter {
+            config: config.clone(),
+            wtr,
+            snapshots: 0,
+            last_snapshot: Instant::now(),
+        })
+    }
+
+    /// Called after each transaction, writes a snapshot if one is due
+    pub(crate) fn after_transaction(&mut self, engine: &mut Engine) -> Result<(), Box<dyn Error>> {
+        let due = match self.config.interval {
+            SnapshotInterval::Rows(interval) => engine.rows().is_multiple_of(interval),
+            SnapshotInterval::Time(interval) => self.last_snapshot.elapsed() >= interval,
+        };
+        if !due {
+            return Ok(());
+        }
+
+        self.snapshots += 1;
+        self.last_snapshot = Instant::now();
+        let prefix = (self.snapshots, engine.rows());
+        if self.config.changed_only {
+            for client_id in engine.drain_dirty() {
+                if let Some(output) = engine.client_output(client_id) {
+                    self.wtr.serialize((prefix.0, prefix.1, output))?;
+                }
+            }
+        } else {
+            for output in engine.client_outputs() {
+                self.wtr.serialize((prefix.0, prefix.1, output))?;
+            }
+        }
+        // make every snapshot visible right away, they are meant to show progress
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
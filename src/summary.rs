@@ -0,0 +1,196 @@
+use crate::rejection::Rejection;
+use crate::transaction::TransactionType;
+use crate::Engine;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Counts of a single input file
+#[derive(Debug, Serialize)]
+pub(crate) struct FileStatistics {
+    file: String,
+    rows: u64,
+    /// transactions that changed the state of a client
+    applied: u64,
+    /// transactions that were invalid or belonged to locked clients
+    ignored: u64,
+}
+
+impl FileStatistics {
+    pub(crate) fn new(file: &Path) -> Self {
+        FileStatistics {
+            file: file.display().to_string(),
+            rows: 0,
+            applied: 0,
+            ignored: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, applied: bool) {
+        self.rows += 1;
+        if applied {
+            self.applied += 1;
+        } else {
+            self.ignored += 1;
+        }
+    }
+}
+
+/// Prints the statistics of all input files as csv to stderr
+pub(crate) fn print_summary(files: &[FileStatistics]) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(io::stderr());
+    for file in files {
+        wtr.serialize(file)?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Where the metrics of a run are written to
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricsOutput {
+    /// as csv with the columns `metric,value`
+    Stderr,
+    Json(PathBuf),
+}
+
+/// Applied and rejected transactions of one type
+#[derive(Debug, Default, Serialize)]
+struct TypeCounts {
+    applied: u64,
+    rejected: u64,
+}
+
+/// Counts, volumes and throughput of a whole run over all input files
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Metrics {
+    rows: u64,
+    applied: u64,
+    rejected: u64,
+    /// by transaction type
+    types: BTreeMap<&'static str, TypeCounts>,
+    /// by the category of the rejection
+    rejections: BTreeMap<&'static str, u64>,
+    clients: u64,
+    locked_clients: u64,
+    /// deposits with a disputed amount that is neither resolved nor charged back
+    open_disputes: u64,
+    deposited: Decimal,
+    withdrawn: Decimal,
+    charged_back: Decimal,
+    reversed: Decimal,
+    seconds: f64,
+    rows_per_second: f64,
+    archived_deposits: u64,
+    /// estimated peak memory of the archived deposits
+    archive_bytes: u64,
+}
+
+impl Metrics {
+    pub(crate) fn record(&mut self, r#type: TransactionType, result: Result<(), Rejection>) {
+        self.rows += 1;
+        let counts = self.types.entry(r#type.name()).or_default();
+        match result {
+            Ok(()) => {
+                self.applied += 1;
+                counts.applied += 1;
+            }
+            Err(rejection) => {
+                self.rejected += 1;
+                counts.rejected += 1;
+                *self.rejections.entry(rejection.category()).or_default() += 1;
+            }
+        }
+    }
+
+    /// Takes the state of the engine at the end of the run
+    pub(crate) fn finish(&mut self, engine: &Engine, elapsed: Duration) {
+        for client in engine.clients() {
+            self.clients += 1;
+            if client.locked {
+                self.locked_clients += 1;
+            }
+            self.deposited += client.stats.deposited_volume;
+            self.withdrawn += client.stats.withdrawn_volume;
+            self.charged_back += client.stats.charged_back_volume;
+            self.reversed += client.stats.reversed_volume;
+        }
+        self.open_disputes = engine
+            .deposits()
+            .filter(|(_, deposit)| !deposit.disputed().is_zero())
+            .count() as u64;
+        self.archived_deposits = engine.deposits().count() as u64;
+        self.archive_bytes = engine.archive_bytes() as u64;
+        self.seconds = elapsed.as_secs_f64();
+        if self.seconds > 0.0 {
+            self.rows_per_second = self.rows as f64 / self.seconds;
+        }
+    }
+
+    pub(crate) fn write(&self, output: &MetricsOutput) -> Result<(), Box<dyn Error>> {
+        match output {
+            MetricsOutput::Stderr => {
+                let mut wtr = csv::Writer::from_writer(io::stderr());
+                wtr.write_record(["metric", "value"])?;
+                for (metric, value) in self.flatten() {
+                    wtr.write_record([metric, value])?;
+                }
+                wtr.flush()?;
+            }
+            MetricsOutput::Json(path) => {
+                let mut writer = BufWriter::new(File::create(path)?);
+                serde_json::to_writer_pretty(&mut writer, self)?;
+                writer.write_all(b"\n")?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Metrics as name and value, nested metrics are named like `type.deposit.applied`
+    fn flatten(&self) -> Vec<(String, String)> {
+        let mut metrics = vec![
+            ("rows".to_string(), self.rows.to_string()),
+            ("applied".to_string(), self.applied.to_string()),
+            ("rejected".to_string(), self.rejected.to_string()),
+        ];
+        for (name, counts) in &self.types {
+            metrics.push((format!("type.{}.applied", name), counts.applied.to_string()));
+            metrics.push((
+                format!("type.{}.rejected", name),
+                counts.rejected.to_string(),
+            ));
+        }
+        for (category, count) in &self.rejections {
+            metrics.push((format!("rejection.{}", category), count.to_string()));
+        }
+        metrics.extend(vec![
+            ("clients".to_string(), self.clients.to_string()),
+            (
+                "locked_clients".to_string(),
+                self.locked_clients.to_string(),
+            ),
+            ("open_disputes".to_string(), self.open_disputes.to_string()),
+            ("deposited".to_string(), self.deposited.to_string()),
+            ("withdrawn".to_string(), self.withdrawn.to_string()),
+            ("charged_back".to_string(), self.charged_back.to_string()),
+            ("reversed".to_string(), self.reversed.to_string()),
+            ("seconds".to_string(), format!("{:.3}", self.seconds)),
+            (
+                "rows_per_second".to_string(),
+                format!("{:.0}", self.rows_per_second),
+            ),
+            (
+                "archived_deposits".to_string(),
+                self.archived_deposits.to_string(),
+            ),
+            ("archive_bytes".to_string(), self.archive_bytes.to_string()),
+        ]);
+        metrics
+    }
+}
@@ -1,22 +1,74 @@
 use rust_decimal::Decimal;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+/// Id of a client, u32 with the `wide-ids` feature.
+/// Ids stay integers so the FxHashMap lookups keep their speed, see `cargo bench --bench id_width`.
+#[cfg(not(feature = "wide-ids"))]
+pub type ClientId = u16;
+#[cfg(feature = "wide-ids")]
+pub type ClientId = u32;
+
+/// Id of a transaction, u64 with the `wide-ids` feature
+#[cfg(not(feature = "wide-ids"))]
+pub type TxId = u32;
+#[cfg(feature = "wide-ids")]
+pub type TxId = u64;
 
 #[derive(Debug, Deserialize)]
-pub(crate) struct Transaction {
+pub struct Transaction {
     pub r#type: TransactionType,
     #[serde(rename = "client")]
-    pub client_id: u16,
+    pub client_id: ClientId,
     #[serde(rename = "tx")]
-    pub tx_id: u32,
+    pub tx_id: TxId,
     pub amount: Option<Decimal>,
+    /// optional column, e.g. seconds since the epoch or a business day, used for checkpoints and point in time queries
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// position of the transaction in the input, set by the engine when processing it
+    #[serde(skip)]
+    pub row: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
-pub(crate) enum TransactionType {
+pub enum TransactionType {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    /// restores the funds of a chargeback, e.g. after a successful representment by the merchant
+    Reversal,
+}
+
+impl TransactionType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Reversal => "reversal",
+        }
+    }
+}
+
+impl Transaction {
+    pub fn new(
+        r#type: TransactionType,
+        client_id: ClientId,
+        tx_id: TxId,
+        amount: Option<Decimal>,
+    ) -> Self {
+        Transaction {
+            r#type,
+            client_id,
+            tx_id,
+            amount,
+            timestamp: None,
+            row: 0,
+        }
+    }
 }
@@ -0,0 +1,83 @@
+use crate::rejection::Rejection;
+use crate::transaction::{Transaction, TransactionType, TxId};
+use crate::{Config, Engine};
+use rustc_hash::FxHashSet;
+use std::error::Error;
+use std::io::Write;
+
+/// Decimal places the engine works with, amounts with more places are reported
+const MAX_SCALE: u32 = 4;
+
+/// Checks every transaction of an input by processing it in an engine whose state is never output
+pub(crate) struct Validator {
+    engine: Engine,
+    /// ids of all deposits and withdrawals, they share one id space
+    tx_ids: FxHashSet<TxId>,
+    /// lines of every category, in the order the categories were first found
+    problems: Vec<(&'static str, Vec<u64>)>,
+}
+
+impl Validator {
+    pub(crate) fn new(config: Config) -> Self {
+        Validator {
+            engine: Engine::new(config),
+            tx_ids: FxHashSet::default(),
+            problems: Vec::new(),
+        }
+    }
+
+    /// A row that couldn't be parsed into a transaction
+    pub(crate) fn schema_error(&mut self, line: u64) {
+        self.report("schema", line);
+    }
+
+    pub(crate) fn check(&mut self, transaction: Transaction, line: u64) {
+        if let Some(amount) = transaction.amount {
+            // trailing zeros like in 1.00000 are accepted by the engine without rounding
+            if amount.normalize().scale() > MAX_SCALE {
+                self.report("precision", line);
+            }
+        }
+        let duplicate = matches!(
+            transaction.r#type,
+            TransactionType::Deposit | TransactionType::Withdrawal
+        ) && !self.tx_ids.insert(transaction.tx_id);
+        if duplicate {
+            self.report("duplicate_tx", line);
+        }
+
+        match self.engine.process(transaction) {
+            // the engine rejects duplicate deposits as well, they are reported once
+            Err(Rejection::DuplicateTx) if duplicate => {}
+            Err(rejection) => self.report(rejection.category(), line),
+            Ok(()) => {}
+        }
+    }
+
+    fn report(&mut self, category: &'static str, line: u64) {
+        match self.problems.iter_mut().find(|(name, _)| *name == category) {
+            Some((_, lines)) => lines.push(line),
+            None => self.problems.push((category, vec![line])),
+        }
+    }
+
+    pub(crate) fn problem_count(&self) -> usize {
+        self.problems.iter().map(|(_, lines)| lines.len()).sum()
+    }
+
+    /// Writes the number of problems and their lines for every category as csv
+    pub(crate) fn write_summary(&self, writer: impl Write) -> Result<(), Box<dyn Error>> {
+        let mut wtr = csv::Writer::from_writer(writer);
+        wtr.write_record(["category", "count", "lines"])?;
+        for (category, lines) in &self.problems {
+            let joined: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+            wtr.write_record([
+                category.to_string(),
+                lines.len().to_string(),
+                joined.join(" "),
+            ])?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo::CommandCargoExt;
+use std::ffi::OsStr;
+use std::process::Command;
+
+/// Asserts that processing the input at path with the given arguments prints the same report as files/simple_input.csv
+pub fn assert_matches_simple_input(
+    args: &[&str],
+    path: impl AsRef<OsStr>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let expected = String::from_utf8(
+        Command::cargo_bin("engine")?
+            .arg("./files/simple_input.csv")
+            .output()?
+            .stdout,
+    )?;
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg(path).args(args);
+    cmd.assert().success().stdout(expected);
+
+    Ok(())
+}
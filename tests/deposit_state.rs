@@ -0,0 +1,38 @@
+use engine_lib::{Config, DepositState, Engine, Transaction, TransactionType, TxId};
+use rust_decimal::Decimal;
+
+#[test]
+fn query_charged_back_deposits() {
+    let mut engine = Engine::new(Config::default());
+    let transactions = vec![
+        (TransactionType::Deposit, 1, 1, Some(Decimal::new(5, 0))),
+        (TransactionType::Deposit, 2, 2, Some(Decimal::new(3, 0))),
+        (TransactionType::Deposit, 2, 3, Some(Decimal::new(1, 0))),
+        (TransactionType::Dispute, 1, 1, None),
+        (TransactionType::Dispute, 2, 2, None),
+        (TransactionType::Resolve, 2, 2, None),
+        (TransactionType::Dispute, 2, 3, None),
+        (TransactionType::Chargeback, 2, 3, None),
+    ];
+    for (r#type, client, tx, amount) in transactions {
+        engine
+            .process(Transaction::new(r#type, client, tx, amount))
+            .unwrap();
+    }
+
+    assert_eq!(engine.deposit(1).unwrap().state(), DepositState::Disputed);
+    assert_eq!(engine.deposit(2).unwrap().state(), DepositState::Settled);
+    assert_eq!(engine.deposit(2).unwrap().history().resolves, 1);
+    assert!(engine.deposit(4).is_none());
+
+    let charged_back: Vec<TxId> = engine
+        .deposits()
+        .filter(|(_, deposit)| deposit.state() == DepositState::ChargedBack)
+        .map(|(tx_id, _)| tx_id)
+        .collect();
+    assert_eq!(charged_back, vec![3]);
+    assert_eq!(
+        engine.deposit(3).unwrap().history().last_charged_back_at,
+        Some(8)
+    );
+}
@@ -0,0 +1,146 @@
+#![cfg(feature = "parquet")]
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Decimal128Type, UInt64Type};
+use arrow_array::{
+    ArrayRef, Decimal128Array, RecordBatch, StringArray, UInt16Array, UInt32Array, UInt64Array,
+};
+use arrow_schema::DataType;
+use assert_cmd::assert::OutputAssertExt;
+use assert_cmd::cargo::CommandCargoExt;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use predicates::prelude::predicate;
+use std::fs::File;
+use std::process::Command;
+use std::sync::Arc;
+
+mod common;
+use common::assert_matches_simple_input;
+
+/// the transactions of files/simple_input.csv with amounts as decimal128 at scale 4
+fn simple_input() -> Result<RecordBatch, Box<dyn std::error::Error>> {
+    let amounts = Decimal128Array::from(vec![10000, 20000, 20000, 15000, 30000])
+        .with_precision_and_scale(38, 4)?;
+    Ok(RecordBatch::try_from_iter(vec![
+        (
+            "type",
+            Arc::new(StringArray::from(vec![
+                "deposit",
+                "deposit",
+                "deposit",
+                "withdrawal",
+                "withdrawal",
+            ])) as ArrayRef,
+        ),
+        ("client", Arc::new(UInt16Array::from(vec![1, 2, 1, 1, 2]))),
+        ("tx", Arc::new(UInt32Array::from(vec![1, 2, 3, 4, 5]))),
+        ("amount", Arc::new(amounts)),
+    ])?)
+}
+
+#[test]
+fn parquet_input_matches_csv() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join("engine_parquet_input_matches_csv.parquet");
+    let batch = simple_input()?;
+    let mut writer = ArrowWriter::try_new(File::create(&path)?, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    assert_matches_simple_input(&[], &path)
+}
+
+#[test]
+fn parquet_report() -> Result<(), Box<dyn std::error::Error>> {
+    let path = std::env::temp_dir().join("engine_parquet_report.parquet");
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/simple_input.csv")
+        .arg("--parquet-report")
+        .arg(&path);
+    cmd.assert().success().stdout("");
+
+    let mut batches = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?.build()?;
+    let batch = batches.next().unwrap()?;
+    assert_eq!(
+        batch.schema().field_with_name("total")?.data_type(),
+        &DataType::Decimal128(38, 4)
+    );
+
+    // the width of client ids depends on the wide-ids feature
+    let clients = arrow_cast::cast(batch.column_by_name("client").unwrap(), &DataType::UInt64)?;
+    let clients = clients.as_primitive::<UInt64Type>();
+    let totals = batch
+        .column_by_name("total")
+        .unwrap()
+        .as_primitive::<Decimal128Type>();
+    let mut rows: Vec<(u64, i128)> = clients
+        .values()
+        .iter()
+        .copied()
+        .zip(totals.values().iter().copied())
+        .collect();
+    rows.sort();
+    assert_eq!(rows, vec![(1, 15000), (2, 20000)]);
+
+    Ok(())
+}
+
+#[test]
+fn parquet_report_rejects_amounts_it_would_round() -> Result<(), Box<dyn std::error::Error>> {
+    let input = std::env::temp_dir().join("engine_parquet_report_precision.csv");
+    let path = std::env::temp_dir().join("engine_parquet_report_precision.parquet");
+    std::fs::write(&input, "type,client,tx,amount\ndeposit,1,1,1.123456\n")?;
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg(&input).arg("--parquet-report").arg(&path);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "the amount 1.123456 of client 1 has more than 4 decimal places",
+    ));
+
+    // trailing zeros beyond the scale don't lose anything
+    std::fs::write(&input, "type,client,tx,amount\ndeposit,1,1,1.123400\n")?;
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg(&input).arg("--parquet-report").arg(&path);
+    cmd.assert().success();
+
+    Ok(())
+}
+
+#[test]
+fn parquet_ids_that_dont_fit() -> Result<(), Box<dyn std::error::Error>> {
+    let batches = [
+        // too big for client ids with and without the wide-ids feature
+        (
+            "too_big",
+            Some(5_000_000_000),
+            "Can't cast value 5000000000",
+        ),
+        ("missing", None, "parquet input has rows without client"),
+    ];
+    for (name, client, error) in batches {
+        let path = std::env::temp_dir().join(format!("engine_parquet_client_{}.parquet", name));
+        let batch = RecordBatch::try_from_iter(vec![
+            (
+                "type",
+                Arc::new(StringArray::from(vec!["deposit"])) as ArrayRef,
+            ),
+            ("client", Arc::new(UInt64Array::from(vec![client]))),
+            ("tx", Arc::new(UInt32Array::from(vec![1]))),
+            (
+                "amount",
+                Arc::new(Decimal128Array::from(vec![50000]).with_precision_and_scale(38, 4)?),
+            ),
+        ])?;
+        let mut writer = ArrowWriter::try_new(File::create(&path)?, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+
+        let mut cmd = Command::cargo_bin("engine")?;
+        cmd.arg(&path);
+        cmd.assert()
+            .failure()
+            .stdout("")
+            .stderr(predicate::str::contains(error));
+    }
+
+    Ok(())
+}
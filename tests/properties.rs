@@ -0,0 +1,241 @@
+use engine_lib::{
+    ClientId, Config, DepositState, Engine, LedgerEntry, LedgerEvent, Rejection, Transaction,
+    TransactionType, TransitionError, TxId,
+};
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// Balances of a client as the reference model computes them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct ModelClient {
+    available: Decimal,
+    held: Decimal,
+    locked: bool,
+    charged_back: Decimal,
+    reversed: Decimal,
+}
+
+struct ModelDeposit {
+    client: ClientId,
+    amount: Decimal,
+    disputed: Decimal,
+    charged_back: Decimal,
+    state: DepositState,
+}
+
+/// Straightforward implementation of the rules in the README, without any of the optimizations of the engine
+#[derive(Default)]
+struct Model {
+    unlock_on_reversal: bool,
+    clients: HashMap<ClientId, ModelClient>,
+    deposits: HashMap<TxId, ModelDeposit>,
+}
+
+impl Model {
+    fn process(&mut self, transaction: &Transaction) -> Result<(), Rejection> {
+        let client = self.clients.entry(transaction.client_id).or_default();
+        if client.locked && transaction.r#type != TransactionType::Reversal {
+            return Err(Rejection::ClientLocked);
+        }
+
+        match transaction.r#type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                let amount = transaction.amount.ok_or(Rejection::MissingAmount)?;
+                if amount.is_sign_negative() {
+                    return Err(Rejection::NegativeAmount);
+                }
+                if amount > Decimal::new(1_000_000_000_000_000, 0) {
+                    return Err(Rejection::AmountTooLarge);
+                }
+                if transaction.r#type == TransactionType::Withdrawal {
+                    if client.available < amount {
+                        return Err(Rejection::InsufficientFunds);
+                    }
+                    client.available -= amount;
+                    return Ok(());
+                }
+                if self.deposits.contains_key(&transaction.tx_id) {
+                    return Err(Rejection::DuplicateTx);
+                }
+                client.available += amount;
+                self.deposits.insert(
+                    transaction.tx_id,
+                    ModelDeposit {
+                        client: transaction.client_id,
+                        amount,
+                        disputed: Decimal::ZERO,
+                        charged_back: Decimal::ZERO,
+                        state: DepositState::Settled,
+                    },
+                );
+            }
+            r#type => {
+                let deposit = self
+                    .deposits
+                    .get_mut(&transaction.tx_id)
+                    .ok_or(Rejection::UnknownDeposit)?;
+                if deposit.client != transaction.client_id {
+                    return Err(TransitionError::ClientMismatch.into());
+                }
+                let (allowed, limit): (&[DepositState], Decimal) = match r#type {
+                    TransactionType::Dispute => (
+                        &[
+                            DepositState::Settled,
+                            DepositState::Disputed,
+                            DepositState::Reversed,
+                        ],
+                        deposit.amount - deposit.charged_back - deposit.disputed,
+                    ),
+                    TransactionType::Resolve | TransactionType::Chargeback => {
+                        (&[DepositState::Disputed], deposit.disputed)
+                    }
+                    _ => (&[DepositState::ChargedBack], deposit.charged_back),
+                };
+                if !allowed.contains(&deposit.state) {
+                    return Err(TransitionError::InvalidState(deposit.state).into());
+                }
+                let amount = transaction.amount.unwrap_or(limit);
+                if amount <= Decimal::ZERO || amount > limit {
+                    return Err(TransitionError::InvalidAmount.into());
+                }
+
+                match r#type {
+                    TransactionType::Dispute => {
+                        deposit.disputed += amount;
+                        deposit.state = DepositState::Disputed;
+                        client.available -= amount;
+                        client.held += amount;
+                    }
+                    TransactionType::Resolve => {
+                        deposit.disputed -= amount;
+                        if deposit.disputed.is_zero() {
+                            deposit.state = if deposit.charged_back.is_zero() {
+                                DepositState::Settled
+                            } else {
+                                DepositState::ChargedBack
+                            };
+                        }
+                        client.held -= amount;
+                        client.available += amount;
+                    }
+                    TransactionType::Chargeback => {
+                        deposit.disputed -= amount;
+                        deposit.charged_back += amount;
+                        if deposit.disputed.is_zero() {
+                            deposit.state = DepositState::ChargedBack;
+                        }
+                        client.held -= amount;
+                        client.locked = true;
+                        client.charged_back += amount;
+                    }
+                    _ => {
+                        deposit.charged_back -= amount;
+                        if deposit.charged_back.is_zero() {
+                            deposit.state = DepositState::Reversed;
+                        }
+                        client.available += amount;
+                        client.reversed += amount;
+                        if self.unlock_on_reversal && client.charged_back == client.reversed {
+                            client.locked = false;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn transaction_type() -> impl Strategy<Value = TransactionType> {
+    prop_oneof![
+        4 => Just(TransactionType::Deposit),
+        3 => Just(TransactionType::Withdrawal),
+        3 => Just(TransactionType::Dispute),
+        1 => Just(TransactionType::Resolve),
+        1 => Just(TransactionType::Chargeback),
+        1 => Just(TransactionType::Reversal),
+    ]
+}
+
+/// few clients, transaction ids and small amounts, so references, partial amounts and overdrafts are frequent
+fn transaction() -> impl Strategy<Value = Transaction> {
+    (
+        transaction_type(),
+        1..=3 as ClientId,
+        1..=8 as TxId,
+        proptest::option::weighted(0.7, -20i64..=100),
+    )
+        .prop_map(|(r#type, client, tx, amount)| {
+            Transaction::new(
+                r#type,
+                client,
+                tx,
+                amount.map(|amount| Decimal::new(amount, 1)),
+            )
+        })
+}
+
+fn balances(entry: &LedgerEntry) -> (Decimal, Decimal, bool) {
+    (entry.available, entry.held, entry.locked)
+}
+
+proptest! {
+    #[test]
+    fn engine_matches_reference_model(
+        transactions in proptest::collection::vec(transaction(), 1..100),
+        unlock_on_reversal in any::<bool>(),
+    ) {
+        let config = Config {
+            unlock_on_reversal,
+            ..Config::default()
+        };
+        let mut engine = Engine::new(config).with_ledger().with_journal();
+        let mut model = Model {
+            unlock_on_reversal,
+            ..Model::default()
+        };
+
+        for transaction in transactions {
+            let (r#type, client_id) = (transaction.r#type, transaction.client_id);
+            let before = model.clients.get(&client_id).copied().unwrap_or_default();
+
+            let expected = model.process(&transaction);
+            let result = engine.process(transaction);
+            prop_assert_eq!(result, expected);
+            let entries: Vec<LedgerEntry> = engine.drain_ledger().collect();
+            let after = model.clients[&client_id];
+
+            // a rejected transaction leaves the client unchanged
+            if result.is_err() {
+                prop_assert!(entries.is_empty());
+                prop_assert_eq!(after, before);
+            }
+            // locked clients only change through reversals
+            if before.locked && r#type != TransactionType::Reversal {
+                prop_assert_eq!(result, Err(Rejection::ClientLocked));
+            }
+
+            let mut total = before.available + before.held;
+            for entry in &entries {
+                prop_assert_eq!(entry.client_id, client_id);
+                prop_assert_eq!(entry.total, entry.available + entry.held);
+                prop_assert!(entry.held >= Decimal::ZERO);
+                if let LedgerEvent::DebitHeld(_) = entry.event {
+                    prop_assert!(entry.total < total);
+                }
+                total = entry.total;
+            }
+            if let Some(last) = entries.last() {
+                prop_assert_eq!(balances(last), (after.available, after.held, after.locked));
+            }
+
+            // the double-entry journal stays balanced and in line with the clients
+            let journal_entries = engine.drain_journal().count();
+            let moved_funds = entries.iter().filter(|entry| entry.event.amount().is_some()).count();
+            prop_assert_eq!(journal_entries, moved_funds);
+            let violations = engine.journal_violations();
+            prop_assert!(violations.is_empty(), "{:?}", violations);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+//! Runs every `files/scenarios/*.scenario` through the engine of the library and compares the
+//! rejected rows and the final state of every client with the expectations of the scenario.
+//!
+//! A scenario consists of sections, `#` starts a comment line:
+//! - `[config]`: optional `key = value` lines, see `apply_config`
+//! - `[transactions]`: the input as csv with a header
+//! - `[rejected]`: optional csv with the columns `row,reason`, the reason is the category of the rejection.
+//!   Rows are counted from 1 without the header, rows that aren't listed have to be applied.
+//! - `[clients]`: csv with the columns `client,available,held,total,locked` and optionally `flagged,flag_reason`.
+//!   Every client of the engine has to be listed, in any order. Amounts are compared by value, `2` equals `2.0`.
+
+use engine_lib::{ClientId, ClientOutput, Config, Engine, Transaction};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+struct ExpectedRejection {
+    row: u64,
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExpectedClient {
+    client: ClientId,
+    available: String,
+    held: String,
+    total: String,
+    locked: bool,
+    flagged: Option<bool>,
+    flag_reason: Option<String>,
+}
+
+/// Lines of every section by name, without comments and blank lines
+fn sections(text: &str) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut sections = BTreeMap::new();
+    let mut current: Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            if sections.insert(name.to_string(), String::new()).is_some() {
+                return Err(format!("section [{}] appears twice", name).into());
+            }
+            current = Some(name.to_string());
+            continue;
+        }
+        match &current {
+            Some(name) => {
+                let section = sections.get_mut(name).expect("section was inserted");
+                section.push_str(line);
+                section.push('\n');
+            }
+            None => return Err(format!("line outside of a section: {}", line).into()),
+        }
+    }
+    for name in sections.keys() {
+        if !["config", "transactions", "rejected", "clients"].contains(&name.as_str()) {
+            return Err(format!("unknown section [{}]", name).into());
+        }
+    }
+    Ok(sections)
+}
+
+fn apply_config(config: &mut Config, section: &str) -> Result<(), Box<dyn Error>> {
+    for line in section.lines() {
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("config expects key = value, got {}", line))?;
+        let value = value.trim();
+        let ratio = || Decimal::from_str(value).map(Some);
+        match key.trim() {
+            "unlock_on_reversal" => config.unlock_on_reversal = value.parse()?,
+            "min_deposits" => config.fraud.min_deposits = value.parse()?,
+            "flag_dispute_ratio" => config.fraud.flag_dispute_ratio = ratio()?,
+            "lock_dispute_ratio" => config.fraud.lock_dispute_ratio = ratio()?,
+            "flag_chargeback_ratio" => config.fraud.flag_chargeback_ratio = ratio()?,
+            "lock_chargeback_ratio" => config.fraud.lock_chargeback_ratio = ratio()?,
+            key => return Err(format!("unknown config key {}", key).into()),
+        }
+    }
+    Ok(())
+}
+
+fn csv_rows<T: serde::de::DeserializeOwned>(section: &str) -> Result<Vec<T>, csv::Error> {
+    csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(section.as_bytes())
+        .deserialize()
+        .collect()
+}
+
+fn compare_amount(
+    mismatches: &mut Vec<String>,
+    client: ClientId,
+    field: &str,
+    expected: &str,
+    actual: Decimal,
+) {
+    match Decimal::from_str(expected) {
+        Ok(expected) if expected == actual => {}
+        _ => mismatches.push(format!(
+            "client {} {}: expected {}, got {}",
+            client, field, expected, actual
+        )),
+    }
+}
+
+fn compare_client(mismatches: &mut Vec<String>, expected: &ExpectedClient, actual: &ClientOutput) {
+    let client = expected.client;
+    compare_amount(
+        mismatches,
+        client,
+        "available",
+        &expected.available,
+        actual.available,
+    );
+    compare_amount(mismatches, client, "held", &expected.held, actual.held);
+    compare_amount(mismatches, client, "total", &expected.total, actual.total);
+    if expected.locked != actual.locked {
+        mismatches.push(format!(
+            "client {} locked: expected {}, got {}",
+            client, expected.locked, actual.locked
+        ));
+    }
+    if expected.flagged.is_some() && expected.flagged != actual.flagged {
+        mismatches.push(format!(
+            "client {} flagged: expected {:?}, got {:?}",
+            client, expected.flagged, actual.flagged
+        ));
+    }
+    // empty csv fields are read as None
+    let actual_reason = actual
+        .flag_reason
+        .clone()
+        .filter(|reason| !reason.is_empty());
+    if expected.flagged.is_some() && expected.flag_reason != actual_reason {
+        mismatches.push(format!(
+            "client {} flag_reason: expected {:?}, got {:?}",
+            client, expected.flag_reason, actual_reason
+        ));
+    }
+}
+
+/// Runs the scenario and returns a description of every mismatch
+fn run_scenario(path: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let sections = sections(&fs::read_to_string(path)?)?;
+    let section = |name: &str| sections.get(name).map(String::as_str);
+
+    let mut config = Config::default();
+    apply_config(&mut config, section("config").unwrap_or_default())?;
+    let transactions: Vec<Transaction> =
+        csv_rows(section("transactions").ok_or("missing section [transactions]")?)?;
+    let expected_rejections: Vec<ExpectedRejection> =
+        csv_rows(section("rejected").unwrap_or("row,reason\n"))?;
+    let expected_clients: Vec<ExpectedClient> =
+        csv_rows(section("clients").ok_or("missing section [clients]")?)?;
+
+    let mut engine = Engine::new(config);
+    let mut rejections = BTreeMap::new();
+    for transaction in transactions {
+        if let Err(rejection) = engine.process(transaction) {
+            rejections.insert(engine.rows(), rejection);
+        }
+    }
+
+    let mut mismatches = Vec::new();
+    let mut expected_reasons = BTreeMap::new();
+    for expected in &expected_rejections {
+        expected_reasons.insert(expected.row, expected.reason.as_str());
+    }
+    for row in 1..=engine.rows() {
+        let expected = expected_reasons.get(&row).copied();
+        let actual = rejections.get(&row).map(|rejection| rejection.category());
+        match (expected, actual) {
+            (Some(expected), Some(actual)) if expected != actual => mismatches.push(format!(
+                "row {}: expected rejection {}, got {} ({})",
+                row, expected, actual, rejections[&row]
+            )),
+            (Some(expected), None) => mismatches.push(format!(
+                "row {}: expected rejection {}, but it was applied",
+                row, expected
+            )),
+            (None, Some(actual)) => mismatches.push(format!(
+                "row {}: rejected with {} ({}), but expected to be applied",
+                row, actual, rejections[&row]
+            )),
+            _ => {}
+        }
+    }
+    if let Some(row) = expected_reasons.keys().find(|row| **row > engine.rows()) {
+        mismatches.push(format!(
+            "row {}: expected rejection of a row that doesn't exist",
+            row
+        ));
+    }
+
+    let mut actual_clients: BTreeMap<ClientId, ClientOutput> = engine
+        .client_outputs()
+        .map(|output| (output.client, output))
+        .collect();
+    for expected in &expected_clients {
+        match actual_clients.remove(&expected.client) {
+            Some(actual) => compare_client(&mut mismatches, expected, &actual),
+            None => mismatches.push(format!("client {}: expected, but missing", expected.client)),
+        }
+    }
+    for client in actual_clients.keys() {
+        mismatches.push(format!("client {}: unexpected", client));
+    }
+    Ok(mismatches)
+}
+
+#[test]
+fn scenarios() -> Result<(), Box<dyn Error>> {
+    let mut paths: Vec<_> = fs::read_dir("./files/scenarios")?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "scenario")
+    });
+    paths.sort();
+    assert!(!paths.is_empty(), "no scenarios in files/scenarios");
+
+    let mut report = String::new();
+    for path in &paths {
+        let mismatches = match run_scenario(path) {
+            Ok(mismatches) => mismatches,
+            Err(err) => vec![format!("invalid scenario: {}", err)],
+        };
+        if !mismatches.is_empty() {
+            report.push_str(&format!("{}:\n", path.display()));
+            for mismatch in mismatches {
+                report.push_str(&format!("  {}\n", mismatch));
+            }
+        }
+    }
+    assert!(report.is_empty(), "scenarios failed\n{}", report);
+    Ok(())
+}
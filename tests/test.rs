@@ -1,8 +1,11 @@
 use assert_cmd::assert::OutputAssertExt;
 use assert_cmd::cargo::CommandCargoExt;
-use predicates::prelude::predicate;
+use predicates::prelude::{predicate, PredicateBooleanExt};
 use std::process::Command;
 
+mod common;
+use common::assert_matches_simple_input;
+
 #[test]
 fn simple_end_to_end() -> Result<(), Box<dyn std::error::Error>> {
     let mut cmd = Command::cargo_bin("engine")?;
@@ -60,10 +63,744 @@ fn reject_transactions_on_frozen_clients() -> Result<(), Box<dyn std::error::Err
 
 #[test]
 fn test_large_test_file() -> Result<(), Box<dyn std::error::Error>> {
+    let transactions = std::env::temp_dir().join("engine_large_test_file.csv");
+    let expected = std::env::temp_dir().join("engine_large_test_file_expected.csv");
+    let actual = std::env::temp_dir().join("engine_large_test_file_actual.csv");
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("generate")
+        .args(["--seed", "42", "--rows", "100000", "--valid-only"])
+        .arg("--report")
+        .arg(&expected);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    std::fs::write(&transactions, output.stdout)?;
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg(&transactions);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+    std::fs::write(&actual, output.stdout)?;
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("diff").arg(&expected).arg(&actual);
+    cmd.assert()
+        .success()
+        .stdout("client,problem,field,expected,actual\n");
+
+    Ok(())
+}
+
+#[test]
+fn generate_is_deterministic() -> Result<(), Box<dyn std::error::Error>> {
+    let generate = || -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin("engine")?;
+        cmd.arg("generate")
+            .args(["--seed", "3", "--rows", "50", "--clients", "5"])
+            .args(["--weight", "reversal=1"]);
+        let output = cmd.output()?;
+        assert!(output.status.success());
+        Ok(output.stdout)
+    };
+    assert_eq!(generate()?, generate()?);
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("generate").args(["--report", "report.csv"]);
+    cmd.assert().failure();
+
+    Ok(())
+}
+
+#[test]
+fn flag_and_lock_on_dispute_ratio() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/dispute_ratios.csv")
+        .args(["--flag-dispute-ratio", "0.4"])
+        .args(["--lock-dispute-ratio", "0.5"]);
+    cmd.assert().success().stdout(
+        predicate::str::starts_with("client,available,held,total,locked,flagged,flag_reason\n")
+            .and(predicate::str::contains(
+                "1,0,2,2,true,true,dispute ratio 1 exceeds 0.5\n",
+            ))
+            .and(predicate::str::contains(
+                "2,2,2,4,false,true,dispute ratio 0.5 exceeds 0.4\n",
+            ))
+            .and(predicate::str::contains("3,5,0,5,false,false,\n")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reject_ratio_outside_0_and_1() -> Result<(), Box<dyn std::error::Error>> {
+    for ratio in ["-1", "1.5", "NaN"] {
+        let mut cmd = Command::cargo_bin("engine")?;
+        cmd.arg("./files/dispute_ratios.csv")
+            .arg(format!("--flag-dispute-ratio={}", ratio));
+        cmd.assert()
+            .code(2)
+            .stderr(predicate::str::contains("--flag-dispute-ratio"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn partial_disputes() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/partial_disputes.csv");
+    cmd.assert().success().stdout(
+        predicate::str::starts_with("client,available,held,total,locked\n")
+            .and(predicate::str::contains("1,6,0,6,true\n"))
+            .and(predicate::str::contains("2,0.0,5.0,5,false\n")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reversal_keeps_client_locked() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/reversal.csv");
+    cmd.assert().success().stdout(
+        predicate::str::starts_with("client,available,held,total,locked\n")
+            .and(predicate::str::contains("1,5,0,5,true\n"))
+            .and(predicate::str::contains("2,0,0,0,true\n")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn unlock_on_reversal() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/reversal.csv").arg("--unlock-on-reversal");
+    cmd.assert().success().stdout(
+        predicate::str::starts_with("client,available,held,total,locked\n")
+            .and(predicate::str::contains("1,7,0,7,false\n"))
+            .and(predicate::str::contains("2,0,0,0,true\n")),
+    );
+
+    Ok(())
+}
+
+#[test]
+fn export_ledger() -> Result<(), Box<dyn std::error::Error>> {
+    let ledger_path = std::env::temp_dir().join("engine_export_ledger.csv");
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/all_types.csv")
+        .arg("--ledger")
+        .arg(&ledger_path);
+    cmd.assert().success();
+
+    let ledger = std::fs::read_to_string(&ledger_path)?;
+    assert_eq!(
+        ledger,
+        "row,client,tx,event,amount,available,held,total,locked\n\
+         1,1,1,credit_available,5,5,0,5,false\n\
+         2,1,2,debit_available,2,3,0,3,false\n\
+         3,1,1,move_to_held,5,-2,5,3,false\n\
+         4,1,1,release_held,5,3,0,3,false\n\
+         5,1,1,move_to_held,5,-2,5,3,false\n\
+         6,1,1,debit_held,5,-2,0,-2,false\n\
+         6,1,1,lock,,-2,0,-2,true\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn query_client_at_row() -> Result<(), Box<dyn std::error::Error>> {
+    let checkpoint_dir = std::env::temp_dir().join("engine_query_client_at_row");
+    let _ = std::fs::remove_dir_all(&checkpoint_dir);
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/timestamps.csv")
+        .arg("--checkpoint-dir")
+        .arg(&checkpoint_dir)
+        .args(["--checkpoint-every", "2"]);
+    cmd.assert().success();
+    assert!(checkpoint_dir.join("checkpoint-4.json").exists());
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("query")
+        .arg("--checkpoint-dir")
+        .arg(&checkpoint_dir)
+        .args(["--client", "1", "--row", "3"])
+        .arg("./files/timestamps.csv");
+    cmd.assert()
+        .success()
+        .stdout("client,available,held,total,locked\n1,7,0,7,false\n");
+
+    Ok(())
+}
+
+#[test]
+fn query_client_at_timestamp() -> Result<(), Box<dyn std::error::Error>> {
+    let checkpoint_dir = std::env::temp_dir().join("engine_query_client_at_timestamp");
+    let _ = std::fs::remove_dir_all(&checkpoint_dir);
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/timestamps.csv")
+        .arg("--checkpoint-dir")
+        .arg(&checkpoint_dir)
+        .arg("--checkpoint-on-timestamp");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("query")
+        .arg("--checkpoint-dir")
+        .arg(&checkpoint_dir)
+        .args(["--client", "2", "--timestamp", "101"])
+        .arg("./files/timestamps.csv");
+    cmd.assert()
+        .success()
+        .stdout("client,available,held,total,locked\n2,0,5,5,false\n");
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("query")
+        .arg("--checkpoint-dir")
+        .arg(&checkpoint_dir)
+        .args(["--client", "2", "--timestamp", "102"])
+        .arg("./files/timestamps.csv");
+    cmd.assert()
+        .success()
+        .stdout("client,available,held,total,locked\n2,5,0,5,false\n");
+
+    Ok(())
+}
+
+#[test]
+fn query_refuses_checkpoint_of_other_inputs() -> Result<(), Box<dyn std::error::Error>> {
+    let checkpoint_dir = std::env::temp_dir().join("engine_query_other_inputs");
+    let _ = std::fs::remove_dir_all(&checkpoint_dir);
+
+    // the rows of the checkpoints are counted across both files
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/acquirers")
+        .arg("--checkpoint-dir")
+        .arg(&checkpoint_dir)
+        .args(["--checkpoint-every", "2"]);
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("query")
+        .arg("--checkpoint-dir")
+        .arg(&checkpoint_dir)
+        .args(["--client", "1", "--row", "5"])
+        .arg("./files/acquirers/b.csv");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("was written for the inputs"));
+
+    Ok(())
+}
+
+#[test]
+fn reject_zero_checkpoint_interval() -> Result<(), Box<dyn std::error::Error>> {
+    let checkpoint_dir = std::env::temp_dir().join("engine_zero_checkpoint_interval");
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/simple_input.csv")
+        .arg("--checkpoint-dir")
+        .arg(&checkpoint_dir)
+        .args(["--checkpoint-every", "0"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--checkpoint-every"));
+
+    Ok(())
+}
+
+#[test]
+fn reject_zero_snapshot_interval() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_path = std::env::temp_dir().join("engine_zero_snapshot_interval.csv");
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/simple_input.csv")
+        .arg("--snapshot-file")
+        .arg(&snapshot_path)
+        .args(["--snapshot-every", "0"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--snapshot-every"));
+
+    Ok(())
+}
+
+#[test]
+fn reject_invalid_snapshot_seconds() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_path = std::env::temp_dir().join("engine_invalid_snapshot_seconds.csv");
+    for seconds in ["0", "-1", "NaN", "inf", "1e30"] {
+        let mut cmd = Command::cargo_bin("engine")?;
+        cmd.arg("./files/simple_input.csv")
+            .arg("--snapshot-file")
+            .arg(&snapshot_path)
+            .arg(format!("--snapshot-every-secs={}", seconds));
+        cmd.assert()
+            .code(2)
+            .stderr(predicate::str::contains("--snapshot-every-secs"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_changed_clients() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_path = std::env::temp_dir().join("engine_snapshot_changed_clients.csv");
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/timestamps.csv")
+        .arg("--snapshot-file")
+        .arg(&snapshot_path)
+        .args(["--snapshot-every", "2", "--snapshot-changed-only"]);
+    cmd.assert().success();
+
+    // the last row doesn't complete an interval
+    let snapshots = std::fs::read_to_string(&snapshot_path)?;
+    assert_eq!(
+        snapshots,
+        "snapshot,row,client,available,held,total,locked\n\
+         1,2,1,10,0,10,false\n\
+         1,2,2,5,0,5,false\n\
+         2,4,1,7,0,7,false\n\
+         2,4,2,0,5,5,false\n\
+         3,6,1,8,0,8,false\n\
+         3,6,2,5,0,5,false\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_all_clients() -> Result<(), Box<dyn std::error::Error>> {
+    let snapshot_path = std::env::temp_dir().join("engine_snapshot_all_clients.csv");
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/simple_input.csv")
+        .arg("--snapshot-file")
+        .arg(&snapshot_path)
+        .args(["--snapshot-every", "1"]);
+    cmd.assert().success();
+
+    let snapshots = std::fs::read_to_string(&snapshot_path)?;
+    let lines: Vec<&str> = snapshots.lines().collect();
+    // one client after the first row, two after each of the other four rows
+    assert_eq!(lines.len(), 1 + 1 + 4 * 2);
+    assert_eq!(lines[1], "1,1,1,1,0,1,false");
+    assert!(lines[9..].contains(&"5,5,2,2,0,2,false"));
+
+    Ok(())
+}
+
+#[test]
+fn process_directory_sequentially() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/acquirers").arg("--summary");
+    // the dispute in a.csv comes before the deposit in b.csv, so it is ignored
+    cmd.assert()
+        .success()
+        .stdout(
+            predicate::str::contains("1,15,0,15,false\n")
+                .and(predicate::str::contains("2,1,0,1,false\n")),
+        )
+        .stderr(
+            "file,rows,applied,ignored\n\
+             ./files/acquirers/a.csv,3,2,1\n\
+             ./files/acquirers/b.csv,3,2,1\n",
+        );
+
+    Ok(())
+}
+
+#[test]
+fn merge_inputs_by_timestamp() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/acquirers/b.csv")
+        .arg("./files/acquirers/a.csv")
+        .args(["--order", "timestamp", "--summary"]);
+    cmd.assert()
+        .success()
+        .stdout(
+            predicate::str::contains("1,15,0,15,false\n")
+                .and(predicate::str::contains("2,1,0,1,false\n")),
+        )
+        .stderr(
+            "file,rows,applied,ignored\n\
+             ./files/acquirers/b.csv,3,3,0\n\
+             ./files/acquirers/a.csv,3,3,0\n",
+        );
+
+    Ok(())
+}
+
+#[test]
+fn merge_by_timestamp_requires_timestamps() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/simple_input.csv")
+        .arg("./files/acquirers/a.csv")
+        .args(["--order", "timestamp"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("has no timestamp"));
+
+    Ok(())
+}
+
+#[test]
+fn compressed_inputs_match_plain_inputs() -> Result<(), Box<dyn std::error::Error>> {
+    for fixture in [
+        "simple_input",
+        "white_space",
+        "all_types",
+        "all_precisions",
+        "reject_on_frozen_client",
+    ] {
+        let plain = format!("./files/{}.csv", fixture);
+        let expected =
+            String::from_utf8(Command::cargo_bin("engine")?.arg(&plain).output()?.stdout)?;
+
+        for extension in ["gz", "zst"] {
+            let mut cmd = Command::cargo_bin("engine")?;
+            cmd.arg(format!("{}.{}", plain, extension));
+            cmd.assert().success().stdout(expected.clone());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn json_lines_match_csv() -> Result<(), Box<dyn std::error::Error>> {
+    assert_matches_simple_input(&[], "./files/simple_input.jsonl")
+}
+
+#[test]
+fn json_lines_detected_without_extension() -> Result<(), Box<dyn std::error::Error>> {
+    // without a known extension the format is detected by the first character
+    let renamed = std::env::temp_dir().join("engine_json_lines_match_csv.txt");
+    std::fs::copy("./files/simple_input.jsonl", &renamed)?;
+    assert_matches_simple_input(&[], &renamed)
+}
+
+#[test]
+fn malformed_json_line() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/malformed.jsonl");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("line 2: "));
+
+    Ok(())
+}
+
+#[test]
+fn partner_dialect() -> Result<(), Box<dyn std::error::Error>> {
+    assert_matches_simple_input(
+        &[
+            "--delimiter",
+            ";",
+            "--column",
+            "TYPE=type",
+            "--column",
+            "client_id=client",
+            "--column",
+            "txid=tx",
+            "--case-insensitive-types",
+            "--decimal-comma",
+        ],
+        "./files/partner_dialect.csv",
+    )
+}
+
+#[test]
+fn no_headers() -> Result<(), Box<dyn std::error::Error>> {
+    assert_matches_simple_input(&["--no-headers"], "./files/no_headers.csv")
+}
+
+#[test]
+fn duplicate_deposit_keeps_archived_deposit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/duplicate_deposit.csv").arg("--audit");
+    cmd.assert().success().stdout(
+        predicate::str::contains("1,10,0,10,false\n")
+            .and(predicate::str::contains("2,0,0,0,false\n")),
+    );
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("validate").arg("./files/duplicate_deposit.csv");
+    cmd.assert()
+        .failure()
+        .stdout("category,count,lines\nduplicate_tx,1,4\n");
+
+    Ok(())
+}
+
+#[test]
+fn validate_input() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("validate").arg("./files/invalid_input.csv");
+    cmd.assert()
+        .failure()
+        .stdout(
+            "category,count,lines\n\
+             duplicate_tx,1,3\n\
+             negative_amount,1,4\n\
+             insufficient_funds,1,5\n\
+             schema,1,6\n\
+             precision,1,7\n\
+             unknown_deposit,1,8\n\
+             client_mismatch,1,9\n\
+             missing_amount,1,10\n",
+        )
+        .stderr(predicate::str::contains("found 8 problems in 10 rows"));
+
     let mut cmd = Command::cargo_bin("engine")?;
-    cmd.arg("./files/large_test_file.csv");
+    cmd.arg("validate").arg("./files/all_types.csv");
+    cmd.assert().success().stdout("category,count,lines\n");
 
+    Ok(())
+}
+
+#[test]
+fn explain_locked_client() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("explain")
+        .arg("./files/dispute_ratios.csv")
+        .args(["--client", "1"])
+        .args(["--lock-dispute-ratio", "0.5"]);
+    cmd.assert().success().stdout(
+        "row,type,tx,amount,outcome,reason,events,available,held,total,locked\n\
+         1,deposit,1,1,applied,,credit_available,1,0,1,false\n\
+         2,deposit,2,1,applied,,credit_available,2,0,2,false\n\
+         6,dispute,1,,applied,,move_to_held,1,1,2,false\n\
+         8,dispute,2,,applied,,move_to_held lock,0,2,2,true\n\
+         9,deposit,5,10,rejected,client is locked,,0,2,2,true\n\
+         \n\
+         client,available,held,total,locked,flagged,flag_reason\n\
+         1,0,2,2,true,true,dispute ratio 1 exceeds 0.5\n",
+    );
+
+    Ok(())
+}
+
+#[test]
+fn diff_reports() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("diff")
+        .arg("./files/reports/expected.csv")
+        .arg("./files/reports/actual.csv");
+    cmd.assert()
+        .failure()
+        .stdout(
+            "client,problem,field,expected,actual\n\
+             3,mismatch,locked,false,true\n\
+             4,missing_in_actual,,,\n\
+             5,missing_in_expected,,,\n",
+        )
+        .stderr(predicate::str::contains("found 3 differences"));
+
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("diff")
+        .arg("./files/reports/actual.csv")
+        .arg("./files/reports/actual.csv");
+    cmd.assert()
+        .success()
+        .stdout("client,problem,field,expected,actual\n");
+
+    Ok(())
+}
+
+#[test]
+fn metrics() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/reversal.csv").arg("--metrics");
+    cmd.assert().success().stderr(
+        predicate::str::starts_with("metric,value\nrows,10\napplied,7\nrejected,3\n")
+            .and(predicate::str::contains(
+                "type.deposit.applied,2\ntype.deposit.rejected,2\n",
+            ))
+            .and(predicate::str::contains(
+                "rejection.client_locked,2\nrejection.unknown_deposit,1\n",
+            ))
+            .and(predicate::str::contains(
+                "clients,2\nlocked_clients,2\nopen_disputes,0\n\
+                 deposited,6\nwithdrawn,0\ncharged_back,6\nreversed,5\n",
+            )),
+    );
+
+    let metrics_path = std::env::temp_dir().join("engine_metrics.json");
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/partial_disputes.csv")
+        .arg("--metrics-json")
+        .arg(&metrics_path);
+    cmd.assert().success().stderr("");
+    let metrics: serde_json::Value = serde_json::from_slice(&std::fs::read(&metrics_path)?)?;
+    assert_eq!(metrics["rows"], 8);
+    assert_eq!(metrics["types"]["dispute"]["applied"], 4);
+    assert_eq!(metrics["locked_clients"], 1);
+    assert_eq!(metrics["open_disputes"], 1);
+    assert_eq!(metrics["charged_back"], "4");
+    assert!(metrics["archive_bytes"].as_u64().unwrap() > 0);
+
+    Ok(())
+}
+
+/// Sends a GET request to the metrics server and returns the whole response
+fn scrape(address: &str, path: &str) -> std::io::Result<String> {
+    use std::io::{Read, Write};
+    let mut stream = std::net::TcpStream::connect(address)?;
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, address);
+    stream.write_all(request.as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[test]
+fn serve_metrics() -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::process::Stdio;
+
+    let mut child = Command::cargo_bin("engine")?
+        .args(["serve", "--metrics-address", "127.0.0.1:0"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line)?;
+    let address = line
+        .trim()
+        .strip_prefix("serving metrics on http://")
+        .and_then(|line| line.strip_suffix("/metrics"))
+        .ok_or_else(|| format!("unexpected first line {}", line))?
+        .to_string();
+
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(
+        b"type,client,tx,amount\n\
+          deposit,1,1,5\n\
+          deposit,2,2,3\n\
+          dispute,1,1,\n\
+          withdrawal,2,3,10\n",
+    )?;
+    stdin.flush()?;
+
+    // the rows are processed while the input is still open
+    let mut metrics = String::new();
+    for _ in 0..100 {
+        metrics = scrape(&address, "/metrics")?;
+        if metrics.contains("engine_processing_seconds_count 4\n") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    assert!(metrics.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(metrics.contains("engine_transactions_total{type=\"deposit\",outcome=\"applied\"} 2\n"));
+    assert!(metrics.contains(
+        "engine_transactions_total{type=\"withdrawal\",outcome=\"insufficient_funds\"} 1\n"
+    ));
+    assert!(metrics.contains("engine_clients 2\n"));
+    assert!(metrics.contains("engine_open_disputes 1\n"));
+    assert!(metrics.contains("engine_held_funds 5\n"));
+    assert!(metrics.contains("engine_processing_seconds_count 4\n"));
+    assert!(scrape(&address, "/other")?.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    drop(stdin);
+    let output = child.wait_with_output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.starts_with("client,available,held,total,locked\n"));
+    assert!(stdout.contains("1,0,5,5,false\n"));
+    assert!(stdout.contains("2,3,0,3,false\n"));
+
+    Ok(())
+}
+
+#[test]
+fn json_logs() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.env("RUST_LOG", "engine_lib=debug")
+        .arg("./files/reversal.csv")
+        .args(["--log-format", "json"]);
+    let output = cmd.output()?;
+    assert!(output.status.success());
+
+    let events: Vec<serde_json::Value> = output
+        .stderr
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_slice)
+        .collect::<Result<_, _>>()?;
+    let transactions: Vec<&serde_json::Value> = events
+        .iter()
+        .filter(|event| event["fields"]["message"] == "transaction processed")
+        .collect();
+    assert_eq!(transactions.len(), 10);
+    let locked = transactions[3];
+    assert_eq!(locked["level"], "DEBUG");
+    assert_eq!(locked["fields"]["client"], 1);
+    assert_eq!(locked["fields"]["tx"], 2);
+    assert_eq!(locked["fields"]["type"], "deposit");
+    assert_eq!(locked["fields"]["outcome"], "client_locked");
+    assert_eq!(locked["span"]["name"], "process_row");
+    assert_eq!(locked["span"]["row"], 4);
+
+    // without RUST_LOG nothing is logged
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.env_remove("RUST_LOG").arg("./files/reversal.csv");
+    cmd.assert().success().stderr("");
+
+    Ok(())
+}
+
+#[test]
+fn journal_and_trial_balance() -> Result<(), Box<dyn std::error::Error>> {
+    let journal_path = std::env::temp_dir().join("engine_journal.csv");
+    let trial_balance_path = std::env::temp_dir().join("engine_trial_balance.csv");
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/reversal.csv")
+        .arg("--journal")
+        .arg(&journal_path)
+        .arg("--trial-balance")
+        .arg(&trial_balance_path);
     cmd.assert().success();
 
+    let journal = std::fs::read_to_string(&journal_path)?;
+    assert!(journal.starts_with(
+        "row,tx,account,client,debit,credit\n\
+         1,1,house_cash,,5,\n\
+         1,1,client_available,1,,5\n"
+    ));
+    assert!(journal.contains(
+        "3,1,client_held,1,5,\n\
+         3,1,chargeback_loss,,,5\n\
+         5,1,chargeback_loss,,5,\n\
+         5,1,client_available,1,,5\n"
+    ));
+    assert_eq!(
+        std::fs::read_to_string(&trial_balance_path)?,
+        "account,client,debit,credit\n\
+         house_cash,,6,0\n\
+         chargeback_loss,,0,1\n\
+         client_available,1,0,5\n\
+         client_available,2,0,0\n\
+         client_held,1,0,0\n\
+         client_held,2,0,0\n\
+         total,,6,6\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn audit() -> Result<(), Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin("engine")?;
+    cmd.arg("./files/partial_disputes.csv").arg("--audit");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::starts_with(
+            "client,available,held,total,locked\n",
+        ))
+        .stderr("client,tx,invariant,expected,actual\n");
+
     Ok(())
 }